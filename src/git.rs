@@ -0,0 +1,748 @@
+//! Running git: the errors of commands, timeouts, credentials and the [`Repository`] of a clone.
+
+use crate::provider::{is_ssh_url, parse_remote_url, Provider};
+use crate::Context;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+/// Failure of a git or build command
+#[derive(Debug)]
+pub enum Error {
+    /// The command could not be executed at all
+    Io(std::io::Error),
+    /// The command exited unsuccessfully, contains its output
+    Failed(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Failed(stderr) => write!(f, "{}", stderr),
+        }
+    }
+}
+
+impl Error {
+    /// Whether the failure looks like a network hiccup, which may succeed if retried
+    pub(crate) fn is_transient(&self) -> bool {
+        const PERMANENT: &[&str] = &["repository not found", "not found", "authentication failed"];
+        const TRANSIENT: &[&str] = &[
+            "could not resolve host",
+            "timed out",
+            "connection reset",
+            "connection refused",
+            "early eof",
+            "temporary failure in name resolution",
+        ];
+
+        match self {
+            Self::Io(_) => false,
+            Self::Failed(output) => {
+                let output = output.to_lowercase();
+                !PERMANENT.iter().any(|keyword| output.contains(keyword))
+                    && TRANSIENT.iter().any(|keyword| output.contains(keyword))
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Executes the command and turns an unsuccessful exit status into an error
+/// carrying the output of the command
+pub(crate) fn execute(command: &mut Command) -> Result<Output, Error> {
+    let output = command.output().map_err(|e| {
+        if command.get_program() == "git" {
+            git_not_found(e)
+        } else {
+            e
+        }
+    })?;
+
+    check(output)
+}
+
+/// Replaces the error of a missing git executable with one which says what to do about it
+fn git_not_found(e: std::io::Error) -> std::io::Error {
+    use std::io::{self, ErrorKind};
+
+    if e.kind() != ErrorKind::NotFound {
+        return e;
+    }
+
+    io::Error::new(
+        ErrorKind::NotFound,
+        "git executable not found, is it installed and on the PATH?",
+    )
+}
+
+/// Fails if git can't be run at all, e.g. because it is not installed
+pub fn check_git(ctx: &Context) -> Result<(), String> {
+    match git(ctx, &["--version"], None) {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!("git --version exited with {}", output.status)),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Turns an unsuccessful exit status into an error carrying the output of the command
+fn check(output: Output) -> Result<Output, Error> {
+    if output.status.success() {
+        return Ok(output);
+    }
+
+    // Some tools, e.g. make, report errors on stdout
+    let message = [&output.stderr, &output.stdout]
+        .iter()
+        .map(|out| String::from_utf8_lossy(out).trim().to_string())
+        .find(|out| !out.is_empty())
+        .unwrap_or_else(|| format!("exited with {}", output.status));
+
+    // git fails instead of prompting, see `git_command`
+    if message.contains("terminal prompts disabled") {
+        return Err(Error::Failed(format!(
+            "{}\nthe repository requires credentials, \
+             configure an access token or pass --interactive to enter them",
+            message
+        )));
+    }
+
+    Err(Error::Failed(message))
+}
+
+/// Like `Command::output`, but kills the command if it runs longer than the timeout.
+/// Stdin is closed, so git can't wait for input which will never come.
+fn output_with_timeout(
+    command: &mut Command,
+    timeout: Option<Duration>,
+) -> std::io::Result<Output> {
+    use std::io::{self, Read};
+    use std::process::Stdio;
+    use std::thread;
+
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return command.output(),
+    };
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Drain both pipes concurrently, a full pipe would block the child
+    let drain = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
+            }
+            buffer
+        })
+    };
+    let stdout = drain(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = drain(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            child.wait()?;
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("git did not finish within {:?} and was killed", timeout),
+            ));
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
+
+pub(crate) fn git_command(ctx: &Context, args: &[&str], abs_path: Option<&Path>) -> Command {
+    let mut command = Command::new("git");
+    if let Some(abs_path) = abs_path {
+        command.current_dir(abs_path);
+    }
+    command.args(args);
+    if !ctx.interactive {
+        command
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .env("GIT_ASKPASS", "")
+            .env("SSH_ASKPASS", "");
+    }
+    if !ctx.auth.is_empty() {
+        // Keep config which is already passed this way
+        let count: usize = std::env::var("GIT_CONFIG_COUNT")
+            .ok()
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0);
+        for (i, (key, value)) in ctx.auth.iter().enumerate() {
+            command.env(format!("GIT_CONFIG_KEY_{}", count + i), key);
+            command.env(format!("GIT_CONFIG_VALUE_{}", count + i), value);
+        }
+        command.env("GIT_CONFIG_COUNT", (count + ctx.auth.len()).to_string());
+    }
+
+    command
+}
+
+pub(crate) fn git(
+    ctx: &Context,
+    args: &[&str],
+    abs_path: Option<&Path>,
+) -> std::io::Result<Output> {
+    output_with_timeout(&mut git_command(ctx, args, abs_path), ctx.timeout).map_err(git_not_found)
+}
+
+/// Runs git in the repository and fails if git does
+pub(crate) fn git_checked(ctx: &Context, args: &[&str], abs_path: &Path) -> Result<Output, Error> {
+    check(git(ctx, args, Some(abs_path))?)
+}
+
+/// Executes the command, or only logs it if this is a dry run
+pub(crate) fn execute_unless_dry_run(command: &mut Command, dry_run: bool) -> Result<(), Error> {
+    if dry_run {
+        log_would_run(command);
+        Ok(())
+    } else {
+        execute(command).map(|_| ())
+    }
+}
+
+/// Logs the command instead of executing it
+fn log_would_run(command: &Command) {
+    let mut line = command.get_program().to_string_lossy().to_string();
+    for arg in command.get_args() {
+        line.push(' ');
+        line.push_str(&arg.to_string_lossy());
+    }
+    match command.get_current_dir() {
+        Some(dir) => log::info!("  Would run `{}` in {:?}", line, dir),
+        None => log::info!("  Would run `{}`", line),
+    }
+}
+
+/// Runs git in the repository unless this is a dry run, see `execute_unless_dry_run`
+pub(crate) fn git_unless_dry_run(
+    ctx: &Context,
+    args: &[&str],
+    abs_path: &Path,
+) -> Result<(), Error> {
+    if ctx.dry_run {
+        log_would_run(&git_command(ctx, args, Some(abs_path)));
+        Ok(())
+    } else {
+        git_checked(ctx, args, abs_path).map(|_| ())
+    }
+}
+
+/// Runs git and returns its trimmed stdout, if git succeeded
+pub(crate) fn git_stdout(ctx: &Context, args: &[&str], abs_path: Option<&Path>) -> Option<String> {
+    git(ctx, args, abs_path)
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+pub(crate) struct Repository<'a> {
+    pub(crate) local_path: PathBuf,
+    pub(crate) git_path: &'a Path,
+    /// Remote to pull and fetch from instead of the upstream of the current branch
+    pub(crate) remote: Option<String>,
+    /// Whether submodules are cloned and updated as well
+    pub(crate) submodules: bool,
+    /// Whether the repository is a bare mirror of all refs of the remote
+    pub(crate) mirror: bool,
+    pub(crate) ctx: &'a Context,
+}
+
+impl<'a> Repository<'a> {
+    pub(crate) fn exists_local(&self) -> bool {
+        self.local_path.exists()
+    }
+
+    /// Whether HEAD points to a commit instead of a branch
+    pub(crate) fn is_detached(&self) -> bool {
+        git(
+            self.ctx,
+            &["symbolic-ref", "-q", "HEAD"],
+            Some(&self.local_path),
+        )
+        .map(|output| !output.status.success())
+        .unwrap_or(false)
+    }
+
+    /// Whether the working tree has uncommitted or untracked changes
+    pub(crate) fn is_dirty(&self) -> bool {
+        git_stdout(self.ctx, &["status", "--porcelain"], Some(&self.local_path))
+            .map_or(false, |status| !status.is_empty())
+    }
+
+    /// Whether tracked files have uncommitted changes, untracked files don't hinder a pull
+    pub(crate) fn has_changes(&self) -> bool {
+        git_stdout(
+            self.ctx,
+            &["status", "--porcelain", "--untracked-files=no"],
+            Some(&self.local_path),
+        )
+        .map_or(false, |status| !status.is_empty())
+    }
+
+    /// Size of the objects, packs and garbage in bytes as reported by `git count-objects`
+    pub(crate) fn object_size(&self) -> Option<u64> {
+        let counts = git_stdout(self.ctx, &["count-objects", "-v"], Some(&self.local_path))?;
+        let kib: u64 = counts
+            .lines()
+            .filter_map(|line| line.split_once(": "))
+            .filter(|(key, _)| ["size", "size-pack", "size-garbage"].contains(key))
+            .filter_map(|(_, value)| value.parse::<u64>().ok())
+            .sum();
+
+        Some(kib * 1024)
+    }
+
+    pub(crate) fn is_shallow(&self) -> bool {
+        git_stdout(
+            self.ctx,
+            &["rev-parse", "--is-shallow-repository"],
+            Some(&self.local_path),
+        )
+        .map_or(false, |shallow| shallow == "true")
+    }
+
+    pub(crate) fn has_untracked_files(&self) -> bool {
+        git_stdout(
+            self.ctx,
+            &["ls-files", "--others", "--exclude-standard"],
+            Some(&self.local_path),
+        )
+        .map_or(false, |files| !files.is_empty())
+    }
+
+    pub(crate) fn status(&self) -> Option<Status> {
+        git_stdout(
+            self.ctx,
+            &["status", "--porcelain=v2", "--branch"],
+            Some(&self.local_path),
+        )
+        .map(|porcelain| Status::parse(&porcelain))
+    }
+
+    /// Whether origin can be contacted, without prompting for credentials
+    pub(crate) fn is_remote_reachable(&self) -> bool {
+        output_with_timeout(
+            git_command(
+                self.ctx,
+                &["ls-remote", "-q", "origin", "HEAD"],
+                Some(&self.local_path),
+            )
+            .env("GIT_TERMINAL_PROMPT", "0"),
+            self.ctx.timeout,
+        )
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+    }
+
+    /// Whether the revision is HEAD or one of its ancestors, so merging it changes nothing.
+    /// False if the revision does not exist, e.g. if the branch has no upstream
+    pub(crate) fn contains(&self, revision: &str) -> bool {
+        git(
+            self.ctx,
+            &["merge-base", "--is-ancestor", revision, "HEAD"],
+            Some(&self.local_path),
+        )
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+    }
+
+    /// The commit checked out, `None` if there is none yet
+    pub(crate) fn head(&self) -> Option<String> {
+        git_stdout(self.ctx, &["rev-parse", "HEAD"], Some(&self.local_path))
+    }
+
+    /// Whether the name is a local branch or one of origin
+    pub(crate) fn is_branch(&self, name: &str) -> bool {
+        self.has_revision(&format!("refs/heads/{}", name))
+            || self.has_revision(&format!("refs/remotes/origin/{}", name))
+    }
+
+    /// The upstream of the local branch, e.g. `origin/main`, if it has one
+    pub(crate) fn upstream(&self, branch: &str) -> Option<String> {
+        git_stdout(
+            self.ctx,
+            &[
+                "rev-parse",
+                "--abbrev-ref",
+                "--symbolic-full-name",
+                &format!("{}@{{u}}", branch),
+            ],
+            Some(&self.local_path),
+        )
+    }
+
+    pub(crate) fn current_branch(&self) -> Option<String> {
+        git_stdout(
+            self.ctx,
+            &["rev-parse", "--abbrev-ref", "HEAD"],
+            Some(&self.local_path),
+        )
+    }
+
+    /// Number of commits HEAD is ahead and behind of the given revision
+    pub(crate) fn ahead_behind(&self, revision: &str) -> Option<(u32, u32)> {
+        let counts = git_stdout(
+            self.ctx,
+            &[
+                "rev-list",
+                "--left-right",
+                "--count",
+                &format!("HEAD...{}", revision),
+            ],
+            Some(&self.local_path),
+        )?;
+        let mut counts = counts.split_whitespace().map(str::parse);
+
+        match (counts.next(), counts.next()) {
+            (Some(Ok(ahead)), Some(Ok(behind))) => Some((ahead, behind)),
+            _ => None,
+        }
+    }
+
+    /// Provider and path of the remote, if it is hosted by a provider
+    pub(crate) fn remote_path(&self, remote: &str) -> Option<(Provider, PathBuf)> {
+        git_stdout(
+            self.ctx,
+            &["remote", "get-url", remote],
+            Some(&self.local_path),
+        )
+        // Local remotes, e.g. a path, are no clones of a provider
+        .filter(|url| url.starts_with("http") || url.starts_with("file://") || is_ssh_url(url))
+        .and_then(|url| parse_remote_url(&url))
+    }
+
+    pub(crate) fn remotes(&self) -> Vec<String> {
+        git_stdout(self.ctx, &["remote"], Some(&self.local_path))
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    /// Whether the revision, e.g. `origin/master`, exists
+    pub(crate) fn has_revision(&self, revision: &str) -> bool {
+        git(
+            self.ctx,
+            &["rev-parse", "--verify", "--quiet", revision],
+            Some(&self.local_path),
+        )
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+    }
+
+    /// The branch `origin/HEAD` points to, e.g. `master`
+    fn default_branch(&self) -> Option<String> {
+        git_stdout(
+            self.ctx,
+            &["symbolic-ref", "--short", "refs/remotes/origin/HEAD"],
+            Some(&self.local_path),
+        )
+        .map(|branch| branch.trim_start_matches("origin/").to_string())
+    }
+
+    /// The latest tag matching the pattern and the number of commits since then
+    pub(crate) fn commits_since_tag(&self, pattern: &str) -> Option<(String, u32)> {
+        let tag = git_stdout(
+            self.ctx,
+            &["describe", "--tags", "--abbrev=0", "--match", pattern],
+            Some(&self.local_path),
+        )?;
+        let count = git_stdout(
+            self.ctx,
+            &["rev-list", "--count", &format!("{}..HEAD", tag)],
+            Some(&self.local_path),
+        )?
+        .parse()
+        .ok()?;
+
+        Some((tag, count))
+    }
+
+    /// The latest commits of HEAD as `<hash> <subject>`, at most `count` and only those
+    /// after `since` if given, e.g. `2 weeks ago` or `2024-01-31`
+    pub(crate) fn recent_commits(&self, count: usize, since: Option<&str>) -> Vec<String> {
+        let count = format!("-n{}", count);
+        let since = since.map(|since| format!("--since={}", since));
+        let mut args = vec!["log", "--oneline", &count];
+        args.extend(since.as_deref());
+        git_stdout(self.ctx, &args, Some(&self.local_path))
+            .map(|log| log.lines().map(String::from).collect())
+            .unwrap_or_default()
+    }
+
+    /// Changes of the working tree and the index compared to HEAD like `git diff --stat`,
+    /// or only the names of the changed files. Untracked files are listed as well
+    pub(crate) fn diff(&self, name_only: bool) -> Vec<String> {
+        let format = if name_only { "--name-only" } else { "--stat" };
+        let diff = git_stdout(self.ctx, &["diff", "HEAD", format], Some(&self.local_path));
+        let untracked = git_stdout(
+            self.ctx,
+            &["ls-files", "--others", "--exclude-standard"],
+            Some(&self.local_path),
+        );
+
+        let mut lines: Vec<String> = diff
+            .unwrap_or_default()
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect();
+        lines.extend(untracked.unwrap_or_default().lines().map(|file| {
+            if name_only {
+                file.to_string()
+            } else {
+                format!("?? {}", file)
+            }
+        }));
+
+        lines
+    }
+
+    /// Tracked files which still contain a Git LFS pointer instead of their content
+    pub(crate) fn lfs_pointers(&self) -> Vec<String> {
+        git_stdout(
+            self.ctx,
+            &[
+                "grep",
+                "-l",
+                "-I",
+                "-e",
+                "^version https://git-lfs\\.github\\.com/spec/v1",
+            ],
+            Some(&self.local_path),
+        )
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.to_string())
+        .collect()
+    }
+
+    /// Local branches which are fully merged into the default branch
+    /// and whose last commit is older than the given amount of days.
+    /// The current and the default branch are never considered stale.
+    pub(crate) fn stale_branches(&self, days: u64) -> Vec<String> {
+        use std::time::{SystemTime, UNIX_EPOCH};
+
+        let default_branch = match self.default_branch() {
+            Some(branch) => branch,
+            None => {
+                log::warn!(
+                    "~ Could not determine default branch of {:?}",
+                    self.git_path
+                );
+                return Vec::new();
+            }
+        };
+        let current_branch = self.current_branch().unwrap_or_default();
+
+        // The local default branch may be behind origin or was never checked out at all
+        let remote_default = format!("origin/{}", default_branch);
+        let base = if self.has_revision(&remote_default) {
+            &remote_default
+        } else {
+            &default_branch
+        };
+        let merged: Vec<String> = match git_checked(
+            self.ctx,
+            &["branch", "--merged", base, "--format=%(refname:short)"],
+            &self.local_path,
+        ) {
+            Ok(output) => String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|line| line.trim().to_string())
+                .collect(),
+            Err(e) => {
+                log::warn!(
+                    "~ Could not list the branches of {:?} merged into {}: {}",
+                    self.git_path,
+                    base,
+                    e
+                );
+                return Vec::new();
+            }
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let max_age = days * 24 * 60 * 60;
+
+        git_stdout(
+            self.ctx,
+            &[
+                "for-each-ref",
+                "--format=%(refname:short) %(committerdate:raw)",
+                "refs/heads",
+            ],
+            Some(&self.local_path),
+        )
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let branch = parts.next()?;
+            let timestamp: u64 = parts.next()?.parse().ok()?;
+
+            Some((branch.to_string(), timestamp))
+        })
+        .filter(|(branch, timestamp)| {
+            *branch != default_branch
+                && *branch != current_branch
+                && merged.contains(branch)
+                && now.saturating_sub(*timestamp) > max_age
+        })
+        .map(|(branch, _)| branch)
+        .collect()
+    }
+}
+
+/// Working tree state of a cloned project as reported by `status`
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Status {
+    /// `None` if HEAD is detached
+    pub(crate) branch: Option<String>,
+    pub(crate) dirty: bool,
+    /// `None` if the branch has no upstream
+    pub(crate) ahead_behind: Option<(u32, u32)>,
+}
+
+impl Status {
+    /// Parses the output of `git status --porcelain=v2 --branch`
+    fn parse(porcelain: &str) -> Self {
+        let mut status = Self::default();
+        for line in porcelain.lines() {
+            match line.strip_prefix("# ") {
+                Some(header) => {
+                    if let Some(branch) = header.strip_prefix("branch.head ") {
+                        if branch != "(detached)" {
+                            status.branch = Some(branch.to_string());
+                        }
+                    } else if let Some(ab) = header.strip_prefix("branch.ab ") {
+                        let mut counts = ab
+                            .split(' ')
+                            .map(|count| count.trim_start_matches(&['+', '-'][..]).parse().ok());
+                        if let (Some(Some(ahead)), Some(Some(behind))) =
+                            (counts.next(), counts.next())
+                        {
+                            status.ahead_behind = Some((ahead, behind));
+                        }
+                    }
+                }
+                None => status.dirty = true,
+            }
+        }
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Layout;
+    use std::ffi::OsStr;
+
+    fn context(layout: Layout) -> Context {
+        let file = Path::new("workspace.toml");
+        let mut ctx = Context::new(file, Some(Path::new("/workspace"))).unwrap();
+        ctx.layout = layout;
+
+        ctx
+    }
+
+    #[test]
+    fn status_from_porcelain() {
+        let status = Status::parse(
+            "# branch.oid 1234\n# branch.head main\n# branch.upstream origin/main\n# branch.ab +2 -3\n? new.txt",
+        );
+
+        assert_eq!(
+            status,
+            Status {
+                branch: Some(String::from("main")),
+                dirty: true,
+                ahead_behind: Some((2, 3)),
+            }
+        );
+    }
+
+    #[test]
+    fn detached_status_without_upstream() {
+        let status = Status::parse("# branch.oid 1234\n# branch.head (detached)");
+
+        assert_eq!(status, Status::default());
+    }
+
+    #[test]
+    fn transient_errors() {
+        let failed = |output: &str| Error::Failed(String::from(output));
+
+        assert!(failed(
+            "fatal: unable to access 'https://github.com/a/b/': Could not resolve host: github.com"
+        )
+        .is_transient());
+        assert!(failed(
+            "error: RPC failed; curl 56 Recv failure: Connection reset by peer\nfatal: early EOF"
+        )
+        .is_transient());
+        assert!(!failed(
+            "fatal: unable to access 'https://github.com/a/b/': The requested URL returned error: 403"
+        )
+        .is_transient());
+        assert!(!failed("fatal: the remote end hung up unexpectedly").is_transient());
+        assert!(!failed(
+            "remote: Repository not found.\nfatal: repository 'https://github.com/a/b/' not found"
+        )
+        .is_transient());
+        assert!(!failed("error: Your local changes would be overwritten by merge").is_transient());
+    }
+
+    #[test]
+    fn git_does_not_prompt() {
+        let mut ctx = context(Layout::Flat);
+        let command = git_command(&ctx, &["fetch"], None);
+        let envs: Vec<_> = command.get_envs().collect();
+        assert!(envs.contains(&(OsStr::new("GIT_TERMINAL_PROMPT"), Some(OsStr::new("0")))));
+        assert!(envs.contains(&(OsStr::new("GIT_ASKPASS"), Some(OsStr::new("")))));
+
+        ctx.interactive = true;
+        assert_eq!(git_command(&ctx, &["fetch"], None).get_envs().count(), 0);
+
+        let error = check(Command::new("sh")
+            .args(["-c", "echo \"fatal: could not read Username: terminal prompts disabled\" >&2; exit 128"])
+            .output()
+            .unwrap())
+        .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("pass --interactive to enter them"),
+            "{}",
+            error
+        );
+    }
+}
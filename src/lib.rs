@@ -0,0 +1,353 @@
+//! Manage a workspace of git repositories described by a `workspace.toml`.
+//!
+//! A [`Workspace`] is loaded with [`Workspace::load`] and operated on with the
+//! [`Git`] operations, e.g. `git_sync`, which return a [`Report`] of every project.
+//! How they run is configured by a [`Context`], which the `workspace` binary builds
+//! from its command line.
+
+mod git;
+mod options;
+mod project;
+mod provider;
+mod report;
+mod workspace;
+
+pub use crate::git::{check_git, Error};
+pub use crate::options::{
+    parse_cmd, validate_cmd, AddOptions, Filter, Format, GraphFormat, Layout, ScanPatterns,
+    SyncStrategy,
+};
+pub use crate::project::{Git, Project};
+pub use crate::provider::{open_in_browser, Provider, WebPage};
+pub use crate::report::{summarize, LogProject, Outcome, ProjectResult, Report};
+pub use crate::workspace::{doctor, Lock, State, Workspace};
+
+use crate::workspace::find_workspace_file;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Expands a leading `~` to the home directory and environment variables written as `${VAR}`
+/// or `$VAR`. An unset `${VAR}` expands to nothing, an unset `$VAR` is kept, e.g. `$1` of a script.
+/// Configured paths and commands are expanded where they are used, so the file stays portable
+fn expand_env(text: &str) -> String {
+    use std::env;
+
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len, braced) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2, true),
+                None => {
+                    expanded.push('$');
+                    rest = after;
+                    continue;
+                }
+            },
+            None => {
+                let len = after
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(after.len());
+                (&after[..len], len, false)
+            }
+        };
+        match env::var(name) {
+            Ok(value) if !name.is_empty() => expanded.push_str(&value),
+            _ if braced => {}
+            _ => expanded.push_str(&rest[start..start + 1 + len]),
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+
+    match (expanded.strip_prefix('~'), env::var("HOME")) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home, rest)
+        }
+        _ => expanded,
+    }
+}
+
+/// Size of all files below the directory in bytes
+fn dir_size(path: &Path) -> u64 {
+    use std::fs;
+
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| match entry.metadata() {
+                    Ok(ref metadata) if metadata.is_dir() => dir_size(&entry.path()),
+                    Ok(metadata) => metadata.len(),
+                    Err(_) => 0,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Settings of the current invocation which are shared by all operations
+pub struct Context {
+    /// Absolute path of the workspace.toml
+    pub file: PathBuf,
+    /// Directory in which the repositories are cloned
+    pub root: PathBuf,
+    /// Whether the root was given by `--root` or `WORKSPACE_ROOT`
+    pub explicit_root: bool,
+    /// Layout of the workspace
+    pub layout: Layout,
+    /// Where to write a JUnit report of the operation, if at all
+    pub report_junit: Option<PathBuf>,
+    /// Where to write a JSON summary of the operation, if at all
+    pub summary_json: Option<PathBuf>,
+    /// Strategy of projects which don't configure their own
+    pub sync_strategy: SyncStrategy,
+    /// Seed to shuffle the order in which the projects are processed
+    pub shuffle: Option<u64>,
+    /// Whether repositories with a detached HEAD are pulled anyway
+    pub force_pull_detached: bool,
+    /// Whether repositories with uncommitted changes are pulled anyway
+    pub force_pull_dirty: bool,
+    /// Whether branches without upstream track the branch of the same name on origin
+    pub set_upstream: bool,
+    /// Whether pull and fetch fail if the url of the remote is not the expected one
+    pub strict: bool,
+    /// Whether uncommitted changes are stashed before pulling and restored afterwards
+    pub stash: bool,
+    /// Whether commands are only logged and nothing is modified
+    pub dry_run: bool,
+    /// Whether changes of the workspace file are only listed instead of saved
+    pub no_save: bool,
+    /// How many of the slowest projects the duration is reported of, if at all
+    pub timings: Option<usize>,
+    /// Maximum number of projects processed in parallel
+    pub jobs: usize,
+    /// Whether a bulk operation stops at the first failing project instead of continuing
+    pub fail_fast: bool,
+    /// Whether destructive operations are confirmed without asking
+    pub yes: bool,
+    /// Whether projects are processed and summarized grouped by provider
+    pub by_provider: bool,
+    /// How often a network operation is retried after a transient failure
+    pub retries: u32,
+    /// Delay before the first retry, doubled on every further attempt
+    pub retry_delay: Duration,
+    /// Whether all projects are cloned via SSH, regardless of their configuration
+    pub ssh: bool,
+    /// Remote to pull and fetch from, regardless of the configuration of the projects
+    pub remote: Option<String>,
+    /// Whether all projects are rebased on pull, regardless of their strategy
+    pub rebase: bool,
+    /// Whether fetch prunes remote-tracking branches deleted on the remote
+    pub prune: bool,
+    /// Whether fetch fetches all tags
+    pub tags: bool,
+    /// Number of commits fetch deepens the history of clones by
+    pub deepen: Option<u32>,
+    /// Whether fetch converts shallow clones into complete ones
+    pub unshallow: bool,
+    /// Output format of informational commands
+    pub format: Format,
+    /// Maximum duration of a single git command, if any
+    pub timeout: Option<Duration>,
+    /// Whether git may prompt for credentials.
+    /// Otherwise git fails right away instead of waiting for input which never comes
+    pub interactive: bool,
+    /// Git config with the access tokens of the providers, see `configure`.
+    /// It is passed via the environment, so the tokens neither show up in the log nor in the
+    /// process list and are never written to the config of a clone
+    pub auth: Vec<(String, String)>,
+}
+
+impl Context {
+    /// The order in which `len` projects are processed
+    fn order(&self, len: usize) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..len).collect();
+        if let Some(seed) = self.shuffle {
+            use rand::rngs::StdRng;
+            use rand::seq::SliceRandom;
+            use rand::SeedableRng;
+
+            order.shuffle(&mut StdRng::seed_from_u64(seed));
+        }
+
+        order
+    }
+
+    /// Runs the network operation `op` of `project`, retrying it with exponential backoff
+    /// as long as it fails transiently and `--retries` are left
+    fn retry<T, F>(&self, project: &Project, op: &str, mut f: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Result<T, Error>,
+    {
+        let mut delay = self.retry_delay;
+        let mut attempt = 0;
+        loop {
+            match f() {
+                Err(e) if attempt < self.retries && e.is_transient() => {
+                    attempt += 1;
+                    log::warn!(
+                        "{} {} failed transiently, retry {} of {} in {:?}",
+                        op,
+                        project.path.display(),
+                        attempt,
+                        self.retries,
+                        delay
+                    );
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Adopts the settings of the loaded workspace file
+    pub fn configure(&mut self, workspace: &Workspace) {
+        self.layout = workspace.layout;
+        self.auth = workspace.auth_config();
+        if let (Some(root), false) = (&workspace.root, self.explicit_root) {
+            let root = PathBuf::from(expand_env(&root.to_string_lossy()));
+            // A relative root is relative to the workspace file, not to the current directory
+            let dir = self.file.parent().unwrap_or_else(|| Path::new("/"));
+            self.root = dir.join(root);
+        }
+    }
+
+    /// Resolves the workspace file and the clone root, all other settings have their defaults.
+    /// The precedence of the root is:
+    /// 1. the given root, e.g. of `--root` or `WORKSPACE_ROOT`
+    /// 2. the `root` of the workspace file, see `configure`
+    /// 3. the directory of the workspace file
+    ///
+    /// A file name without directory is looked up in the current directory and its parents.
+    pub fn new(workspace_file: &Path, root: Option<&Path>) -> std::io::Result<Self> {
+        use std::env;
+        use std::thread;
+
+        let current_dir = env::current_dir()?;
+        let file = find_workspace_file(&current_dir, workspace_file);
+        let explicit_root = root.is_some();
+        // Without a root the repositories are next to the workspace file
+        let root = root.map_or_else(
+            || file.parent().unwrap_or(&current_dir).to_path_buf(),
+            |root| current_dir.join(root),
+        );
+
+        Ok(Self {
+            file,
+            root,
+            explicit_root,
+            layout: Layout::default(),
+            report_junit: None,
+            summary_json: None,
+            sync_strategy: SyncStrategy::Pull,
+            shuffle: None,
+            force_pull_detached: false,
+            force_pull_dirty: false,
+            set_upstream: false,
+            strict: false,
+            stash: false,
+            dry_run: false,
+            no_save: false,
+            timings: None,
+            jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+            fail_fast: false,
+            yes: false,
+            by_provider: false,
+            retries: 0,
+            retry_delay: Duration::from_secs(1),
+            ssh: false,
+            remote: None,
+            rebase: false,
+            prune: false,
+            tags: false,
+            deepen: None,
+            unshallow: false,
+            format: Format::Text,
+            timeout: None,
+            interactive: false,
+            auth: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(layout: Layout) -> Context {
+        let file = Path::new("workspace.toml");
+        let mut ctx = Context::new(file, Some(Path::new("/workspace"))).unwrap();
+        ctx.layout = layout;
+
+        ctx
+    }
+
+    #[test]
+    fn shuffled_order_depends_on_the_seed() {
+        let mut ctx = context(Layout::Flat);
+        assert_eq!(ctx.order(5), vec![0, 1, 2, 3, 4]);
+
+        ctx.shuffle = Some(42);
+        let order = ctx.order(20);
+        assert_eq!(ctx.order(20), order);
+        let mut sorted = order.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+        assert_ne!(order, sorted);
+
+        ctx.shuffle = Some(7);
+        assert_ne!(ctx.order(20), order);
+    }
+
+    #[test]
+    fn root_relative_to_workspace_file() {
+        let workspace: Workspace = toml::from_str("root = \"src\"").unwrap();
+
+        let file = Path::new("/config/workspace.toml");
+        let mut ctx = Context::new(file, None).unwrap();
+        ctx.configure(&workspace);
+        assert_eq!(ctx.root, Path::new("/config/src"));
+
+        let file = Path::new("workspace.toml");
+        let mut ctx = Context::new(file, Some(Path::new("/clones"))).unwrap();
+        ctx.configure(&workspace);
+        assert_eq!(ctx.root, Path::new("/clones"));
+    }
+
+    #[test]
+    fn expand_env_vars() {
+        let home = std::env::var("HOME").unwrap();
+
+        assert_eq!(
+            expand_env("target/${CARGO_PKG_NAME}/${WORKSPACE_UNSET_VAR}"),
+            format!("target/{}/", env!("CARGO_PKG_NAME"))
+        );
+        assert_eq!(expand_env("${unterminated"), "${unterminated");
+        assert_eq!(expand_env("~"), home);
+        assert_eq!(expand_env("~/src"), format!("{}/src", home));
+        assert_eq!(expand_env("$HOME/src"), format!("{}/src", home));
+        assert_eq!(
+            expand_env("${HOME}/$WORKSPACE_UNSET_VAR/~user"),
+            format!("{}/$WORKSPACE_UNSET_VAR/~user", home)
+        );
+        assert_eq!(expand_env("src/$"), "src/$");
+        assert_eq!(expand_env("print $1"), "print $1");
+    }
+}
@@ -1,340 +1,634 @@
-use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
+use std::process::{self, Command};
+use std::time::Duration;
 use structopt::StructOpt;
+use workspace::{
+    check_git, doctor, open_in_browser, summarize, validate_cmd, AddOptions, Context, Filter,
+    Format, Git, GraphFormat, Lock, LogProject, Outcome, Provider, Report, ScanPatterns, State,
+    SyncStrategy, WebPage, Workspace,
+};
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-enum Provider {
-    Github,
+/// When the log is colored
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColorChoice {
+    /// If stdout is a terminal and `NO_COLOR` is not set
+    Auto,
+    Always,
+    Never,
 }
 
-fn git(args: &[&str], abs_path: Option<&Path>) -> std::io::Result<Output> {
-    if let Some(abs_path) = abs_path {
-        Command::new("git")
-            .current_dir(abs_path)
-            .args(args)
-            .output()
-    } else {
-        Command::new("git").args(args).output()
-    }
-}
-
-struct Repository<'a> {
-    local_path: PathBuf,
-    git_path: &'a Path,
-}
+impl std::str::FromStr for ColorChoice {
+    type Err = String;
 
-impl<'a> Repository<'a> {
-    fn exists_local(&self) -> bool {
-        self.local_path.exists()
-    }
-}
-
-impl Provider {
-    fn from(provider: &str) -> Option<Self> {
-        match provider {
-            "github" => Some(Self::Github),
-            "github.com" => Some(Self::Github),
-            _ => None,
-        }
-    }
-
-    fn get_url(&self) -> &str {
-        match *self {
-            Self::Github => "https://github.com",
-        }
-    }
-
-    fn git_pull<'a>(&self, repo: &Repository<'a>) {
-        log::info!("- Pull {:?}...", repo.git_path);
-        match *self {
-            Self::Github => {
-                git(&["pull"], Some(&repo.local_path)).expect("Failed to pull");
-            }
+    fn from_str(choice: &str) -> Result<Self, Self::Err> {
+        match choice {
+            "auto" => Ok(Self::Auto),
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            _ => Err(format!("Unknown color choice {:?}", choice)),
         }
     }
+}
 
-    fn git_clone<'a>(&self, repo: &Repository<'a>) {
-        let url = format!("{}/{}", self.get_url(), repo.git_path.display());
-        log::info!("- Clone {}...", &url);
-        match *self {
-            Self::Github => {
-                git(&["clone", &url], None).expect("Failed to clone");
-            }
-        }
-    }
+impl ColorChoice {
+    fn enabled(self) -> bool {
+        use is_terminal::IsTerminal;
+        use std::env;
+        use std::io;
 
-    fn git_fetch<'a>(&self, repo: &Repository<'a>) {
-        log::info!("- Fetch {:?}...", repo.git_path);
-        match *self {
-            Self::Github => {
-                git(&["fetch"], Some(&repo.local_path)).expect("Failed to fetch");
+        match self {
+            Self::Always => true,
+            Self::Never => false,
+            Self::Auto => {
+                env::var_os("NO_COLOR")
+                    .filter(|value| !value.is_empty())
+                    .is_none()
+                    && io::stdout().is_terminal()
             }
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Project {
-    provider: Provider,
-    path: PathBuf,
-    #[serde(default)]
-    cmd: Vec<String>,
-}
-
-trait Git {
-    fn git_pull(&self);
-    fn git_clone(&self);
-    fn git_fetch(&self);
-    fn git_sync(&self);
+/// Logs to stdout like `simple_logger` without timestamps, but colors the lines by what
+/// they report: errors and failures red, skips and warnings yellow and finished operations green
+struct Logger {
+    level: log::Level,
+    color: bool,
 }
 
-impl Project {
-    fn get_absolute_path(&self) -> PathBuf {
-        use std::env;
-
-        let folder = self.get_path().file_stem().expect("Could not get folder");
-
-        env::current_dir()
-            .expect("Could not get current path")
-            .join(folder)
+impl Logger {
+    fn init(level: log::Level, color: ColorChoice) {
+        let logger = Self {
+            level,
+            color: color.enabled(),
+        };
+        log::set_boxed_logger(Box::new(logger)).expect("Could not init logger");
+        log::set_max_level(level.to_level_filter());
     }
 
-    fn get_path(&self) -> &Path {
-        &self.path
-    }
+    /// ANSI color of the message, depending on the conventions of the log lines
+    fn color(record: &log::Record, message: &str) -> Option<&'static str> {
+        const RED: &str = "31";
+        const GREEN: &str = "32";
+        const YELLOW: &str = "33";
 
-    fn get_repository(&self) -> Repository {
-        Repository {
-            local_path: self.get_absolute_path(),
-            git_path: self.get_path(),
+        match record.level() {
+            log::Level::Error => Some(RED),
+            log::Level::Warn => Some(YELLOW),
+            // Progress of a bulk operation, e.g. `[1/3] pull owner/repo: pulled`
+            _ if message.starts_with('[') && message.ends_with(": failed") => Some(RED),
+            _ if message.starts_with('[') && message.ends_with(": skipped") => Some(YELLOW),
+            _ if message.starts_with('[') => Some(GREEN),
+            _ if message.starts_with('~') => Some(YELLOW),
+            _ => None,
         }
     }
-
-    fn build(&self) {
-        match self.cmd.len() {
-            0 => Ok(()),
-            1 => Command::new(&self.cmd[0])
-                .current_dir(self.get_absolute_path())
-                .output()
-                .and_then(|_| Ok(())),
-            _ => Command::new(&self.cmd[0])
-                .current_dir(self.get_absolute_path())
-                .args(&self.cmd[1..])
-                .output()
-                .and_then(|_| Ok(())),
-        }
-        .expect("Could not build");
-    }
 }
 
-impl Git for Project {
-    fn git_pull(&self) {
-        let repo = self.get_repository();
-        if repo.exists_local() {
-            self.provider.git_pull(&repo);
-        } else {
-            log::info!("~ {:?} is not cloned yet", repo.git_path);
-        }
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= self.level
     }
 
-    fn git_clone(&self) {
-        let repo = self.get_repository();
-        if !repo.exists_local() {
-            self.provider.git_clone(&repo);
-        } else {
-            log::info!("~ {:?} is already cloned", repo.git_path);
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
         }
-    }
 
-    fn git_fetch(&self) {
-        let repo = self.get_repository();
-        if repo.exists_local() {
-            self.provider.git_fetch(&repo);
-        } else {
-            log::info!("~ {:?} is not cloned yet", repo.git_path);
+        let level = format!("{:<5}", record.level());
+        let message = record.args().to_string();
+        // The crate, not the module of the library the line was logged in
+        let target = record.target().split("::").next().unwrap_or_default();
+        let color = Self::color(record, &message).filter(|_| self.color);
+        // Every line, e.g. of the output of a build, belongs to the project
+        let message = match LogProject::current() {
+            Some(path) => message
+                .lines()
+                .map(|line| format!("[{}] {}", path, line))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            None => message,
+        };
+        match color {
+            Some(color) => println!(
+                "\x1b[{}m{}\x1b[0m [{}] \x1b[{}m{}\x1b[0m",
+                color, level, target, color, message
+            ),
+            None => println!("{} [{}] {}", level, target, message),
         }
     }
 
-    fn git_sync(&self) {
-        if self.get_repository().exists_local() {
-            self.git_pull();
-        } else {
-            self.git_clone();
-        }
-    }
+    fn flush(&self) {}
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct Workspace {
-    #[serde(default, rename = "workspace")]
-    projects: Vec<Project>,
-}
+/// Exit status of a bulk operation in which some, but not all projects failed.
+/// Errors before any project is processed, e.g. an invalid workspace file, exit with 1
+const EXIT_SOME_FAILED: i32 = 2;
+/// Exit status of a bulk operation in which every project failed
+const EXIT_ALL_FAILED: i32 = 3;
 
-impl Workspace {
-    fn build(&self) {
-        log::info!("Build...");
-        self.projects.iter().for_each(|project| project.build())
+/// Finishes a bulk operation by writing the requested reports and summarizing failures.
+/// Exits with `EXIT_SOME_FAILED` or `EXIT_ALL_FAILED` if any project failed
+fn conclude(report: Report, ctx: &Context) {
+    if !summarize(&report, ctx) {
+        let all = report.failures().count() == report.results.len();
+        process::exit(if all {
+            EXIT_ALL_FAILED
+        } else {
+            EXIT_SOME_FAILED
+        });
     }
+}
 
-    fn save(&mut self) {
-        use std::fs;
+#[derive(StructOpt, Debug)]
+#[structopt(after_help = "\
+EXIT STATUS:
+    0 if every repository succeeded, 2 if some and 3 if all of them failed.
+    1 if the operation could not start, e.g. due to an invalid workspace file.
 
-        fs::write(
-            "workspace.toml",
-            toml::to_string(&self).expect("Failed save workspace.toml"),
-        )
-        .expect("Unable to write file");
-    }
+ACCESS TOKENS:
+    Private repositories are cloned via HTTPS with a token in WORKSPACE_<PROVIDER>_TOKEN,
+    e.g. WORKSPACE_GITHUB_TOKEN. The host of a self-hosted provider is upper-cased with
+    every other character replaced by `_`, e.g. WORKSPACE_GIT_MYCORP_INTERNAL_TOKEN.
 
-    fn add(&mut self, path: &Path, cmd: Option<String>) -> std::io::Result<()> {
-        use std::env;
+EXTERNAL SUBCOMMANDS:
+    An unknown subcommand runs `workspace-<name>` from the PATH, like git does.
+    WORKSPACE_FILE and WORKSPACE_ROOT are set to the workspace file and the clone root.")]
+struct Opt {
+    #[structopt(
+        long = "file",
+        env = "WORKSPACE_FILE",
+        global = true,
+        default_value = "workspace.toml"
+    )]
+    /// The workspace file, looked up in the current directory and its parents. It is YAML if
+    /// it ends with `.yaml` or `.yml`. Takes precedence over the WORKSPACE_FILE environment
+    /// variable
+    workspace_file: PathBuf,
+    #[structopt(long, env = "WORKSPACE_ROOT", global = true)]
+    /// Directory in which the repositories are cloned, default to current directory.
+    /// Takes precedence over the WORKSPACE_ROOT environment variable
+    root: Option<PathBuf>,
+    #[structopt(long, global = true)]
+    /// Write the results of pull, clone, fetch, sync or build as JUnit XML to this file
+    report_junit: Option<PathBuf>,
+    #[structopt(long, global = true)]
+    /// Write the status, error and duration of every repository of a bulk operation
+    /// as JSON to this file
+    summary_json: Option<PathBuf>,
+    #[structopt(
+        long,
+        global = true,
+        default_value = "pull",
+        possible_values = &["pull", "rebase", "reset", "ff-only"]
+    )]
+    /// How pull and sync update cloned repositories.
+    /// A `sync_strategy` configured for a project takes precedence
+    strategy: SyncStrategy,
+    #[structopt(long, global = true, require_equals = true)]
+    /// Process the repositories in random order, optionally with a fixed seed: `--shuffle=<seed>`
+    shuffle: Option<Option<u64>>,
+    #[structopt(long, global = true)]
+    /// Pull repositories with a detached HEAD instead of skipping them
+    force_pull_detached: bool,
+    #[structopt(long, global = true)]
+    /// Only log the git and build commands which would run, without modifying
+    /// the repositories or the workspace.toml. Push lets git report what would be pushed
+    dry_run: bool,
+    #[structopt(long, global = true)]
+    /// Don't write changes to the workspace file, e.g. of add or scan,
+    /// but list the repositories it would contain. Use `--format json` for details
+    no_save: bool,
+    #[structopt(long, global = true)]
+    /// Report how long the operation took per repository, slowest first
+    timings: bool,
+    #[structopt(long, global = true, value_name = "N")]
+    /// Like --timings, but only report the N slowest repositories
+    slowest: Option<usize>,
+    #[structopt(short, long, global = true)]
+    /// Number of repositories processed in parallel, default to the number of CPUs
+    jobs: Option<usize>,
+    #[structopt(long, global = true)]
+    /// Stop at the first failing repository instead of continuing with the others,
+    /// repositories which were not started yet are reported as skipped
+    fail_fast: bool,
+    #[structopt(short, long, global = true)]
+    /// Confirm destructive operations like reset, clean or rm --delete-local without asking
+    yes: bool,
+    #[structopt(long, global = true)]
+    /// Process the repositories grouped by provider, with a header before every group
+    /// and a subtotal per provider in the summary
+    by_provider: bool,
+    #[structopt(long, global = true)]
+    /// Seconds after which a hanging git command, e.g. one waiting for credentials, is killed
+    timeout: Option<u64>,
+    #[structopt(long, global = true)]
+    /// Let git prompt for credentials instead of failing the repositories which need them
+    interactive: bool,
+    #[structopt(long, global = true, default_value = "0")]
+    /// Retry clone, fetch and pull this often if they fail due to network problems
+    retries: u32,
+    #[structopt(long, global = true, default_value = "1")]
+    /// Seconds to wait before the first retry, doubled on every further retry
+    retry_delay: u64,
+    #[structopt(
+        long,
+        global = true,
+        default_value = "text",
+        possible_values = &["text", "json"]
+    )]
+    /// Output format of list, count, show-builds, health and --timings
+    format: Format,
+    #[structopt(
+        long,
+        global = true,
+        default_value = "auto",
+        possible_values = &["auto", "always", "never"]
+    )]
+    /// Color the log, `auto` colors it if stdout is a terminal and NO_COLOR is not set
+    color: ColorChoice,
+    #[structopt(short, long, global = true, conflicts_with = "verbose")]
+    /// Only log errors, the summaries of the operations are still printed
+    quiet: bool,
+    #[structopt(short, long, global = true, parse(from_occurrences))]
+    /// Log debug details, twice to log everything
+    verbose: u8,
+    #[structopt(subcommand)]
+    cmd: SubCommand,
+}
 
-        let current_dir = env::current_dir()?;
-        let git_path = current_dir.join(path).join(".git");
-        if git_path.exists() {
-            if let Ok(output) = git(&["config", "--get", "remote.origin.url"], Some(path)) {
-                let remote_url = String::from_utf8_lossy(&output.stdout);
-                if let Ok(url) = url::Url::parse(&remote_url) {
-                    if let Some(host) = url.host_str() {
-                        if let Some(provider) = Provider::from(host) {
-                            let cmd = if let Some(cmd) = cmd {
-                                cmd.split(' ').map(|s| s.to_string()).collect()
-                            } else {
-                                Vec::new()
-                            };
-
-                            let path = PathBuf::from(url.path().trim_start_matches('/'));
-                            if self
-                                .projects
-                                .iter()
-                                .position(|p| p.path == path && p.provider == provider)
-                                .is_none()
-                            {
-                                let project = Project {
-                                    provider,
-                                    path,
-                                    cmd,
-                                };
-                                log::info!(
-                                    "Found path {:?} with provider {:?}",
-                                    project.path,
-                                    project.provider
-                                );
-                                self.projects.push(project);
-                            }
-                        } else {
-                            log::error!("Could not identify provider for {:?}", host);
-                        }
-                    } else {
-                        log::error!(
-                            "Invalid remote-url {:?}. Could not determine host.",
-                            remote_url
-                        );
-                    }
-                } else {
-                    log::error!("Could not parse url {:?}", remote_url);
-                }
-            } else {
-                log::error!("Invalid remote for {:?}", path);
-            }
+impl Opt {
+    /// The settings of the global options, `structopt` already falls back to the
+    /// `WORKSPACE_FILE` and `WORKSPACE_ROOT` environment variables
+    fn context(&self) -> std::io::Result<Context> {
+        let mut ctx = Context::new(&self.workspace_file, self.root.as_deref())?;
+        ctx.report_junit = self.report_junit.clone();
+        ctx.summary_json = self.summary_json.clone();
+        ctx.sync_strategy = self.strategy;
+        ctx.force_pull_detached = self.force_pull_detached;
+        ctx.dry_run = self.dry_run;
+        ctx.no_save = self.no_save;
+        ctx.timings = if self.timings {
+            Some(usize::MAX)
         } else {
-            log::warn!("{:?} is not a git repository", path);
+            self.slowest
+        };
+        if let Some(jobs) = self.jobs {
+            ctx.jobs = jobs;
         }
+        ctx.fail_fast = self.fail_fast;
+        ctx.yes = self.yes;
+        ctx.by_provider = self.by_provider;
+        ctx.retries = self.retries;
+        ctx.retry_delay = Duration::from_secs(self.retry_delay);
+        ctx.format = self.format;
+        ctx.timeout = self.timeout.map(Duration::from_secs);
+        ctx.interactive = self.interactive;
+        ctx.shuffle = self.shuffle.map(|seed| {
+            let seed = seed.unwrap_or_else(rand::random);
+            log::info!("Shuffling with seed {}", seed);
 
-        Ok(())
-    }
+            seed
+        });
 
-    fn remove(&mut self, path: &Path, provider: Provider) {
-        if let Some(index) = self
-            .projects
-            .iter()
-            .position(|p| p.path == path && p.provider == provider)
-        {
-            self.projects.remove(index);
-            log::info!("Path {:?} with provider {:?} was removed", path, provider);
-        }
+        Ok(ctx)
     }
 
-    fn scan(&mut self, path: Option<PathBuf>) -> std::io::Result<()> {
-        use std::env;
-        use std::fs;
-
-        let current_dir = env::current_dir()?;
-        let path = path.map_or(current_dir.clone(), |path| current_dir.join(path));
-
-        log::info!("Scanning {:?}...", path);
-
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let path = entry.path();
-            let metadata = fs::metadata(&path)?;
-
-            if !metadata.is_file() {
-                self.add(&path, None).ok();
-            }
+    fn log_level(&self) -> log::Level {
+        match (self.quiet, self.verbose) {
+            (true, _) => log::Level::Error,
+            (false, 0) => log::Level::Info,
+            (false, 1) => log::Level::Debug,
+            (false, _) => log::Level::Trace,
         }
-
-        Ok(())
-    }
-}
-
-impl Git for Workspace {
-    fn git_pull(&self) {
-        log::info!("Pull...");
-        self.projects.iter().for_each(|project| project.git_pull())
-    }
-
-    fn git_clone(&self) {
-        log::info!("Clone...");
-        self.projects.iter().for_each(|project| project.git_clone())
-    }
-
-    fn git_fetch(&self) {
-        log::info!("Fetch...");
-        self.projects.iter().for_each(|project| project.git_fetch())
-    }
-
-    fn git_sync(&self) {
-        log::info!("Synchronize...");
-        self.projects.iter().for_each(|project| project.git_sync());
     }
 }
 
 #[derive(StructOpt, Debug)]
-enum Opt {
+enum SubCommand {
+    #[structopt(name = "init")]
+    /// Create an empty workspace file
+    Init {
+        #[structopt(long)]
+        /// Overwrite an existing workspace file
+        force: bool,
+    },
     #[structopt(name = "pull")]
     /// Pull all cloned repositories
-    Pull,
+    Pull {
+        #[structopt(long)]
+        /// Remote to pull from instead of the configured one
+        remote: Option<String>,
+        #[structopt(long)]
+        /// Rebase all repositories, regardless of their `sync_strategy` and `--strategy`
+        rebase: bool,
+        #[structopt(long)]
+        /// Pull repositories with a detached HEAD or uncommitted changes instead of skipping them
+        force: bool,
+        #[structopt(long, conflicts_with = "force")]
+        /// Stash uncommitted changes before pulling and restore them afterwards
+        stash: bool,
+        #[structopt(long)]
+        /// Let branches without upstream track the branch of the same name on origin
+        /// instead of skipping them
+        set_upstream: bool,
+        #[structopt(long)]
+        /// Fail repositories whose remote url differs from the one the workspace file expects
+        strict: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
     #[structopt(name = "clone")]
     /// Clone all not cloned repositories
-    Clone,
+    Clone {
+        #[structopt(long)]
+        /// Skip cloned repositories entirely instead of reporting them
+        only_missing: bool,
+        #[structopt(long = "continue")]
+        /// Skip the repositories cloned by the previous, interrupted or failed, clone
+        resume: bool,
+        #[structopt(long)]
+        /// Clone all repositories via SSH instead of HTTPS
+        ssh: bool,
+        #[structopt(long)]
+        /// Store the checked out branch of every cloned repository without a branch in the workspace file
+        save_branch: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
     #[structopt(name = "fetch")]
     /// Fetch all cloned repositories
-    Fetch,
+    Fetch {
+        #[structopt(long)]
+        /// Remote to fetch from instead of the configured one
+        remote: Option<String>,
+        #[structopt(long)]
+        /// Remove remote-tracking branches which no longer exist on the remote,
+        /// always done if `prune = true` is set in the workspace.toml
+        prune: bool,
+        #[structopt(long)]
+        /// Fetch all tags, not only those pointing into the fetched history
+        tags: bool,
+        #[structopt(long, value_name = "N", conflicts_with = "unshallow")]
+        /// Fetch N more commits of history into existing clones
+        deepen: Option<u32>,
+        #[structopt(long)]
+        /// Fetch the complete history into shallow clones
+        unshallow: bool,
+        #[structopt(long)]
+        /// Fail repositories whose remote url differs from the one the workspace file expects
+        strict: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    #[structopt(name = "push")]
+    /// Push all cloned repositories with unpushed commits
+    Push {
+        #[structopt(long)]
+        /// Push branches without upstream to origin, or the configured remote, and track them
+        set_upstream: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
     #[structopt(name = "sync")]
     /// Pull all cloned repositories, Clone all not cloned repositories
-    Sync,
+    Sync {
+        #[structopt(long)]
+        /// Remote to pull from instead of the configured one
+        remote: Option<String>,
+        #[structopt(long)]
+        /// Rebase all repositories, regardless of their `sync_strategy` and `--strategy`
+        rebase: bool,
+        #[structopt(long)]
+        /// Pull repositories with a detached HEAD or uncommitted changes instead of skipping them
+        force: bool,
+        #[structopt(long, conflicts_with = "force")]
+        /// Stash uncommitted changes before pulling and restore them afterwards
+        stash: bool,
+        #[structopt(long)]
+        /// Let branches without upstream track the branch of the same name on origin
+        /// instead of skipping them
+        set_upstream: bool,
+        #[structopt(long)]
+        /// Fail repositories whose remote url differs from the one the workspace file expects
+        strict: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    #[structopt(name = "watch")]
+    /// Sync all repositories periodically until interrupted with Ctrl-C
+    Watch {
+        #[structopt(long, default_value = "300")]
+        /// Seconds to wait between two syncs
+        interval: u64,
+        #[structopt(long)]
+        /// Only fetch the cloned repositories instead of syncing them
+        fetch: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
     #[structopt(name = "list")]
     /// List all workspace repositories
     List {
         #[structopt(long)]
         /// List only cloned workspace repositories
         cloned: bool,
+        #[structopt(long)]
+        /// List only repositories which are on another branch than their recorded `branch`
+        off_branch: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    #[structopt(name = "count")]
+    /// Count the repositories and how many of them are cloned, per provider
+    Count {
+        #[structopt(flatten)]
+        filter: Filter,
     },
     #[structopt(name = "build")]
     /// Build all cloned repositories
-    Build,
+    Build {
+        #[structopt(long = "only")]
+        /// Only build the repository with this path or clone directory, can be given multiple times
+        only: Vec<PathBuf>,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    #[structopt(name = "exec")]
+    /// Run a command in all cloned repositories, e.g. `workspace exec --tag web -- npm ci`
+    Exec {
+        #[structopt(flatten)]
+        filter: Filter,
+        #[structopt(required = true)]
+        /// The command and its arguments
+        command: Vec<String>,
+    },
+    #[structopt(name = "clean")]
+    /// Report the untracked files of all cloned repositories, remove them with --force
+    Clean {
+        #[structopt(long)]
+        /// Remove the untracked files without asking. Otherwise they are only reported,
+        /// unless the removal is confirmed on a terminal
+        force: bool,
+        #[structopt(short = "x")]
+        /// Also remove files ignored by git
+        ignored: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    #[structopt(name = "gc")]
+    /// Run `git gc` in all cloned repositories to pack loose objects and reclaim space
+    Gc {
+        #[structopt(long)]
+        /// Optimize the repositories more thoroughly, which takes much longer
+        aggressive: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    #[structopt(name = "reset")]
+    /// Reset all cloned repositories hard to their upstream, discarding local commits and changes
+    Reset {
+        #[structopt(long)]
+        /// Reset without asking for confirmation, required if stdin is not a terminal.
+        /// Use --dry-run to list the commits and changes which would be lost
+        force: bool,
+        #[structopt(long)]
+        /// Also remove untracked files and directories
+        clean: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
     #[structopt(name = "add")]
     /// Add a new repository
     Add {
+        #[structopt(long = "path", required_unless = "urls")]
+        /// Paths of the repositories, the options apply to all of them
+        paths: Vec<PathBuf>,
+        #[structopt(long = "url", conflicts_with = "remote")]
+        /// Remote urls of repositories which are not cloned yet, e.g. `https://github.com/owner/repo`
+        urls: Vec<String>,
+        #[structopt(flatten)]
+        options: AddOptions,
+    },
+    #[structopt(name = "import-urls")]
+    /// Add all repositories listed in a file, one url per line
+    ImportUrls {
+        #[structopt(parse(from_os_str))]
+        /// File containing the urls. Blank lines and lines starting with `#` are ignored
+        file: PathBuf,
+    },
+    #[structopt(name = "import")]
+    /// Add all repositories of a GitHub organization, without cloning them
+    Import {
         #[structopt(long)]
-        /// Path of the repository
-        path: PathBuf,
+        /// Name of the organization. `WORKSPACE_GITHUB_TOKEN` is used to see private repositories
+        org: String,
+    },
+    #[structopt(name = "stale-branches")]
+    /// List local branches which are merged and older than the given amount of days
+    StaleBranches {
+        #[structopt(long, default_value = "30")]
+        /// Minimum age in days of the last commit
+        days: u64,
+        #[structopt(long)]
+        /// Delete the stale branches after confirmation
+        delete: bool,
+    },
+    #[structopt(name = "status")]
+    /// Show branch, uncommitted changes and how far the cloned repositories are ahead or behind
+    Status {
+        #[structopt(long)]
+        /// Compare against the current branch of every remote instead of the upstream only
+        all_remotes: bool,
+        #[structopt(long)]
+        /// Only repositories which are on another branch than their recorded `branch`
+        off_branch: bool,
+        #[structopt(long, conflicts_with = "all-remotes")]
+        /// Print path, branch, ahead, behind and `clean` or `dirty` of every clone
+        /// separated by tabs, one line per clone, for scripts
+        porcelain: bool,
+    },
+    #[structopt(name = "checkout")]
+    /// Switch all cloned repositories to a branch, where it exists
+    Checkout {
+        #[structopt(long)]
+        branch: String,
+    },
+    #[structopt(name = "set-upstream")]
+    /// Track the branch of the same name on origin in every cloned repository
+    SetUpstream {
+        #[structopt(long)]
+        /// Branch to set the upstream for, default to the current branch
+        branch: Option<String>,
+    },
+    #[structopt(name = "doctor")]
+    /// Check that git works, the workspace file is valid and which clones are missing or unknown
+    Doctor,
+    #[structopt(name = "health")]
+    /// Show clone state, changes, divergence, remote reachability and disk usage of all repositories
+    Health,
+    #[structopt(name = "lfs-check")]
+    /// Find cloned repositories containing Git LFS pointers instead of file contents
+    LfsCheck,
+    #[structopt(name = "since-tag")]
+    /// Count the commits since the latest tag of all cloned repositories
+    SinceTag {
+        #[structopt(long, default_value = "*")]
+        /// Glob pattern the tags have to match, e.g. `v*`
+        pattern: String,
+    },
+    #[structopt(name = "log")]
+    /// Show the latest commits of all cloned repositories
+    Log {
+        #[structopt(short = "n", long, default_value = "5")]
+        /// Number of commits per repository
+        count: usize,
         #[structopt(long)]
-        /// Optional build command for the repository
-        cmd: Option<String>,
+        /// Only show commits after the date, e.g. `2 weeks ago` or `2024-01-31`
+        since: Option<String>,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    #[structopt(name = "diff")]
+    /// Show the uncommitted changes of all cloned repositories, leaving out clean ones
+    Diff {
+        #[structopt(long)]
+        /// Only list the names of the changed files
+        name_only: bool,
+        #[structopt(flatten)]
+        filter: Filter,
+    },
+    #[structopt(name = "show-builds")]
+    /// Show the build command of every repository without running it
+    ShowBuilds,
+    #[structopt(name = "set-cmd")]
+    /// Set the build command of all repositories matching a pattern
+    SetCmd {
+        #[structopt(long = "match")]
+        /// Glob pattern the repository path has to match, e.g. `Dgame/*`
+        pattern: String,
+        #[structopt(long, validator = validate_cmd)]
+        /// New build command, arguments can be quoted like in a shell
+        cmd: String,
+    },
+    #[structopt(name = "completions")]
+    /// Print the completion script for the shell to stdout
+    Completions {
+        #[structopt(possible_values = &structopt::clap::Shell::variants(), case_insensitive = true)]
+        shell: structopt::clap::Shell,
+    },
+    #[structopt(name = "freeze")]
+    /// Record the commit of every cloned repository in the lock file next to the workspace.toml
+    Freeze,
+    #[structopt(name = "restore")]
+    /// Check out the commits recorded by freeze, the repositories end up with a detached HEAD
+    Restore,
+    #[structopt(name = "graph")]
+    /// Print the `depends_on` graph of the repositories
+    Graph {
+        #[structopt(long)]
+        /// Print a Mermaid flowchart instead of Graphviz DOT
+        mermaid: bool,
     },
     #[structopt(name = "rm")]
     /// Remove an existing repository
@@ -345,63 +639,590 @@ enum Opt {
         #[structopt(long)]
         /// Provider of the repository
         provider: String,
+        #[structopt(long)]
+        /// Also delete the cloned repository
+        delete_local: bool,
+        #[structopt(long)]
+        /// Delete the cloned repository without asking, even with uncommitted changes
+        force: bool,
+    },
+    #[structopt(name = "info")]
+    /// Show the configuration and clone state of a single repository
+    Info {
+        #[structopt(long)]
+        /// Path of the repository, e.g. `owner/repo`, or its clone directory
+        path: PathBuf,
     },
+    #[structopt(name = "enable")]
+    /// Include a disabled repository in bulk operations again
+    Enable {
+        #[structopt(long)]
+        /// Path of the repository, e.g. `owner/repo`, or its clone directory
+        path: PathBuf,
+    },
+    #[structopt(name = "disable")]
+    /// Skip a repository in bulk operations without removing it from the workspace
+    Disable {
+        #[structopt(long)]
+        /// Path of the repository, e.g. `owner/repo`, or its clone directory
+        path: PathBuf,
+    },
+    #[structopt(name = "mv")]
+    /// Move the clone of a repository and record its new directory in the workspace file
+    Mv {
+        #[structopt(long)]
+        /// Path or clone directory of the repository
+        path: PathBuf,
+        #[structopt(long)]
+        /// New clone directory, relative to the root
+        to: PathBuf,
+    },
+    #[structopt(name = "open")]
+    /// Open the web page of a repository in the browser
+    Open {
+        /// Path or clone directory of the repository, or a part of its path if it is unique
+        path: String,
+        #[structopt(long, conflicts_with_all = &["issues", "pulls"])]
+        /// Open the page of the user or organization the repository belongs to
+        owner: bool,
+        #[structopt(long, conflicts_with = "pulls")]
+        /// Open the issues of the repository
+        issues: bool,
+        #[structopt(long)]
+        /// Open the pull requests, or merge requests, of the repository
+        pulls: bool,
+    },
+    #[structopt(name = "verify")]
+    /// Check that the remote of every clone matches its repository in the workspace.toml
+    Verify {
+        #[structopt(long)]
+        /// Change the repositories in the workspace.toml to match the remotes of their clones
+        fix: bool,
+    },
+    #[structopt(name = "prune")]
+    /// Remove all repositories which are not cloned, e.g. because their clone was deleted
+    Prune,
     #[structopt(name = "scan")]
     /// Scan for repositories and add them to the workspace
     Scan {
         #[structopt(long)]
-        /// Optional path which should be scanned, default to current directory
+        /// Optional path which should be scanned, default to the clone root
         path: Option<PathBuf>,
+        #[structopt(long, default_value = "1")]
+        /// How many directory levels below the path are searched for repositories
+        depth: usize,
+        #[structopt(flatten)]
+        patterns: ScanPatterns,
         #[structopt(long)]
-        /// Only list the found repositories, don't add them to the workspace
-        dry_run: bool,
+        /// Detect the build command of every added repository by its manifest,
+        /// e.g. `cargo build` for a Cargo.toml
+        infer_cmd: bool,
     },
 }
 
-fn main() {
-    use std::fs;
+impl SubCommand {
+    /// Whether the subcommand adds repositories, so the workspace file does not have to exist yet
+    fn adds_projects(&self) -> bool {
+        matches!(
+            self,
+            Self::Add { .. } | Self::Import { .. } | Self::ImportUrls { .. } | Self::Scan { .. }
+        )
+    }
+}
+
+/// Runs `workspace-<name>` from the PATH for a subcommand which is not built in, like git does.
+/// The remaining arguments are passed through, the workspace is described by the environment:
+///  - `WORKSPACE_FILE`: absolute path of the `workspace.toml`
+///  - `WORKSPACE_ROOT`: directory in which the repositories are cloned
+///
+/// Returns the exit code of the external command, or `None` if there is no such command.
+fn dispatch_external(args: &[String], error: &structopt::clap::Error) -> Option<i32> {
+    use std::io::ErrorKind;
+    use structopt::clap::ErrorKind as ClapErrorKind;
 
-    simple_logger::init().expect("Could not init logger");
+    match error.kind {
+        ClapErrorKind::UnknownArgument | ClapErrorKind::UnrecognizedSubcommand => {}
+        _ => return None,
+    }
+    let name = error.info.as_ref()?.first()?;
+    if name.starts_with('-') {
+        return None;
+    }
+    let index = args.iter().position(|arg| arg == name)?;
 
-    let opt = Opt::from_args();
-    let mut workspace = if let Ok(content) = fs::read("workspace.toml") {
-        toml::from_str(&String::from_utf8_lossy(&content)).expect("Could not load Workspace")
-    } else {
-        Workspace::default()
+    // Parse the global options in front of the external subcommand with a built-in one
+    let ctx = Opt::from_iter_safe(args[..index].iter().map(String::as_str).chain(vec!["list"]))
+        .ok()
+        .and_then(|opt| opt.context().ok())?;
+
+    match Command::new(format!("workspace-{}", name))
+        .args(&args[index + 1..])
+        .env("WORKSPACE_FILE", ctx.file)
+        .env("WORKSPACE_ROOT", ctx.root)
+        .status()
+    {
+        Ok(status) => Some(status.code().unwrap_or(1)),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => {
+            log::error!("Could not run workspace-{}: {}", name, e);
+            Some(1)
+        }
+    }
+}
+
+/// Loads the workspace file, exits if it is invalid or, unless `missing_ok`, missing
+fn load(ctx: &Context, missing_ok: bool) -> Workspace {
+    Workspace::load(&ctx.file, missing_ok).unwrap_or_else(|e| {
+        log::error!("{}", e);
+        process::exit(1);
+    })
+}
+
+/// Saves the workspace file like `Workspace::persist`, exits if it can't be written
+fn persist(workspace: &mut Workspace, ctx: &Context) {
+    if let Err(e) = workspace.persist(ctx) {
+        log::error!("Could not write {:?}: {}", ctx.file, e);
+        process::exit(1);
+    }
+}
+
+/// Enables or disables a project and saves the workspace file if it changed
+fn set_enabled(workspace: &mut Workspace, ctx: &Context, path: &Path, enabled: bool) {
+    match workspace.set_enabled(ctx, path, enabled) {
+        Ok(true) => persist(workspace, ctx),
+        Ok(false) => {}
+        Err(e) => {
+            log::error!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    use std::env;
+
+    let args: Vec<String> = env::args().collect();
+    let opt = match Opt::from_iter_safe(&args) {
+        Ok(opt) => opt,
+        Err(e) => {
+            Logger::init(log::Level::Info, ColorChoice::Auto);
+            match dispatch_external(&args, &e) {
+                Some(code) => process::exit(code),
+                None => e.exit(),
+            }
+        }
     };
+    Logger::init(opt.log_level(), opt.color);
+    let mut ctx = opt.context().expect("Could not resolve root directory");
+    if let SubCommand::Init { force } = opt.cmd {
+        if ctx.file.exists() && !force {
+            log::error!("{:?} already exists, use --force to overwrite it", ctx.file);
+            process::exit(1);
+        }
+        if !ctx.dry_run {
+            if let Err(e) = Workspace::default().save(&ctx.file) {
+                log::error!("Could not write {:?}: {}", ctx.file, e);
+                process::exit(1);
+            }
+        }
+        log::info!("Created {:?}", ctx.file);
+        return;
+    }
 
-    match opt {
-        Opt::Pull => workspace.git_pull(),
-        Opt::Clone => workspace.git_clone(),
-        Opt::Fetch => workspace.git_fetch(),
-        Opt::Sync => workspace.git_sync(),
-        Opt::List { cloned } => workspace.projects.iter().for_each(|project| {
-            if cloned {
-                if project.get_repository().exists_local() {
-                    log::info!(" - {}", project.path.display());
+    if let SubCommand::Completions { shell } = opt.cmd {
+        Opt::clap().gen_completions_to("workspace", shell, &mut std::io::stdout());
+        return;
+    }
+    if let SubCommand::Doctor = opt.cmd {
+        process::exit(if doctor(&mut ctx) { 0 } else { 1 });
+    }
+    if let Err(e) = check_git(&ctx) {
+        log::error!("{}", e);
+        process::exit(1);
+    }
+
+    let mut workspace = load(&ctx, opt.cmd.adds_projects());
+    ctx.configure(&workspace);
+
+    match opt.cmd {
+        // Handled before loading the workspace
+        SubCommand::Init { .. } | SubCommand::Completions { .. } | SubCommand::Doctor => {}
+        SubCommand::Pull {
+            remote,
+            rebase,
+            force,
+            stash,
+            set_upstream,
+            strict,
+            filter,
+        } => {
+            ctx.strict = strict;
+            ctx.stash = stash;
+            ctx.remote = remote;
+            ctx.rebase = rebase;
+            ctx.force_pull_detached |= force;
+            ctx.force_pull_dirty = force;
+            ctx.set_upstream = set_upstream;
+            workspace.filter(&filter);
+            conclude(workspace.git_pull(&ctx), &ctx)
+        }
+        SubCommand::Clone {
+            only_missing,
+            resume,
+            ssh,
+            save_branch,
+            filter,
+        } => {
+            ctx.ssh = ssh;
+            workspace.filter(&filter);
+            let mut state = State::load(&ctx);
+            if resume {
+                workspace
+                    .projects
+                    .retain(|project| !state.clone_progress.contains(&project.path));
+            } else {
+                state.clone_progress.clear();
+            }
+            if only_missing {
+                workspace
+                    .projects
+                    .retain(|project| !project.is_cloned(&ctx));
+            }
+            let report = workspace.git_clone_tracked(&ctx, &mut state);
+            if save_branch && !ctx.dry_run {
+                let cloned: Vec<&Path> = report
+                    .results
+                    .iter()
+                    .filter(|r| matches!(r.result, Ok(Outcome::Cloned)))
+                    .map(|r| r.path.as_path())
+                    .collect();
+                // The filtered workspace must not be saved, so the branches go into a fresh copy
+                let mut workspace = load(&ctx, false);
+                if workspace.save_branches(&ctx, &cloned) > 0 {
+                    persist(&mut workspace, &ctx);
+                }
+            }
+            conclude(report, &ctx)
+        }
+        SubCommand::Fetch {
+            remote,
+            prune,
+            tags,
+            deepen,
+            unshallow,
+            strict,
+            filter,
+        } => {
+            ctx.strict = strict;
+            ctx.remote = remote;
+            ctx.prune = prune || workspace.prune;
+            ctx.tags = tags;
+            ctx.deepen = deepen;
+            ctx.unshallow = unshallow;
+            workspace.filter(&filter);
+            conclude(workspace.git_fetch(&ctx), &ctx)
+        }
+        SubCommand::Sync {
+            remote,
+            rebase,
+            force,
+            stash,
+            set_upstream,
+            strict,
+            filter,
+        } => {
+            ctx.strict = strict;
+            ctx.stash = stash;
+            ctx.remote = remote;
+            ctx.rebase = rebase;
+            ctx.force_pull_detached |= force;
+            ctx.force_pull_dirty = force;
+            ctx.set_upstream = set_upstream;
+            workspace.filter(&filter);
+            conclude(workspace.git_sync(&ctx), &ctx)
+        }
+        SubCommand::Watch {
+            interval,
+            fetch,
+            filter,
+        } => {
+            ctx.prune = workspace.prune;
+            workspace.filter(&filter);
+            workspace.watch(&ctx, Duration::from_secs(interval), fetch)
+        }
+        SubCommand::Push {
+            set_upstream,
+            filter,
+        } => {
+            workspace.filter(&filter);
+            conclude(workspace.git_push(&ctx, set_upstream), &ctx)
+        }
+        SubCommand::List {
+            cloned,
+            off_branch,
+            filter,
+        } => {
+            workspace.filter(&filter);
+            if off_branch {
+                workspace.retain_off_branch(&ctx);
+            }
+            workspace.list(&ctx, cloned)
+        }
+        SubCommand::Count { filter } => {
+            workspace.filter(&filter);
+            workspace.count(&ctx)
+        }
+        SubCommand::Build { only, filter } => {
+            if let Err(path) = workspace.select(&ctx, &only) {
+                log::error!("There is no repository {:?} in the workspace", path);
+                process::exit(1);
+            }
+            workspace.filter(&filter);
+            conclude(workspace.build(&ctx), &ctx)
+        }
+        SubCommand::Exec { filter, command } => {
+            workspace.filter(&filter);
+            conclude(workspace.exec(&ctx, &command), &ctx)
+        }
+        SubCommand::Clean {
+            force,
+            ignored,
+            filter,
+        } => {
+            workspace.filter(&filter);
+            // Declined, the files are only reported
+            let force = force
+                || !ctx.dry_run
+                    && workspace.confirm(&ctx, "Clean", |project| {
+                        !project.mirror && project.would_clean(&ctx, ignored)
+                    });
+            conclude(workspace.git_clean(&ctx, force, ignored), &ctx)
+        }
+        SubCommand::Gc { aggressive, filter } => {
+            workspace.filter(&filter);
+            conclude(workspace.git_gc(&ctx, aggressive), &ctx)
+        }
+        SubCommand::Reset {
+            force,
+            clean,
+            filter,
+        } => {
+            workspace.filter(&filter);
+            if !force
+                && !ctx.dry_run
+                && !workspace.confirm(&ctx, "Reset", |project| {
+                    !project.mirror && project.is_cloned(&ctx)
+                })
+            {
+                log::error!(
+                    "Reset discards all local commits and changes, use --force to reset \
+                     or --dry-run to see what would be discarded"
+                );
+                process::exit(1);
+            }
+            conclude(workspace.git_reset(&ctx, clean), &ctx)
+        }
+        SubCommand::ShowBuilds => workspace.show_builds(ctx.format),
+        SubCommand::Add {
+            paths,
+            urls,
+            options,
+        } => {
+            let mut state = State::load(&ctx);
+            for path in &paths {
+                if let Err(e) = workspace.add(&ctx, path, options.clone(), &mut state) {
+                    log::error!("Could not add {:?}: {}", path, e);
+                }
+            }
+            for url in &urls {
+                match workspace.add_url(url, options.clone()) {
+                    Ok(true) => {}
+                    Ok(false) => log::info!("~ {} is already part of the workspace", url),
+                    Err(e) => log::error!("Could not add {}: {}", url, e),
+                }
+            }
+            if !ctx.dry_run {
+                if let Err(e) = state.save() {
+                    log::warn!("Could not write the state file: {}", e);
                 }
+            }
+            persist(&mut workspace, &ctx);
+        }
+        SubCommand::ImportUrls { file } => match workspace.import_urls(&file) {
+            Ok(_) => persist(&mut workspace, &ctx),
+            Err(e) => {
+                log::error!("Could not read {:?}: {}", file, e);
+                process::exit(1);
+            }
+        },
+        SubCommand::Import { org } => match workspace.import_org(&org) {
+            Ok(()) => persist(&mut workspace, &ctx),
+            Err(e) => {
+                log::error!("Could not list the repositories of {}: {}", org, e);
+                process::exit(1);
+            }
+        },
+        SubCommand::StaleBranches { days, delete } => workspace.stale_branches(&ctx, days, delete),
+        SubCommand::Status {
+            all_remotes,
+            off_branch,
+            porcelain,
+        } => {
+            if off_branch {
+                workspace.retain_off_branch(&ctx);
+            }
+            workspace.status(&ctx, all_remotes, porcelain)
+        }
+        SubCommand::Checkout { branch } => conclude(workspace.checkout(&ctx, &branch), &ctx),
+        SubCommand::SetUpstream { branch } => workspace.set_upstream(&ctx, branch.as_deref()),
+        SubCommand::Health => workspace.health(&ctx),
+        SubCommand::LfsCheck => workspace.lfs_check(&ctx),
+        SubCommand::SinceTag { pattern } => workspace.since_tag(&ctx, &pattern),
+        SubCommand::Log {
+            count,
+            since,
+            filter,
+        } => {
+            workspace.filter(&filter);
+            workspace.log(&ctx, count, since.as_deref())
+        }
+        SubCommand::Diff { name_only, filter } => {
+            workspace.filter(&filter);
+            workspace.diff(&ctx, name_only)
+        }
+        SubCommand::SetCmd { pattern, cmd } => {
+            let changed = workspace
+                .set_cmd(&pattern, &cmd, ctx.dry_run)
+                .unwrap_or_else(|e| {
+                    log::error!("{}", e);
+                    process::exit(1);
+                });
+            log::info!("Changed the build command of {} repositories", changed);
+            if changed > 0 {
+                persist(&mut workspace, &ctx);
+            }
+        }
+        SubCommand::Info { path } => {
+            if !workspace.info(&ctx, &path) {
+                log::error!("There is no repository {:?} in the workspace", path);
+                process::exit(1);
+            }
+        }
+        SubCommand::Enable { path } => set_enabled(&mut workspace, &ctx, &path, true),
+        SubCommand::Disable { path } => set_enabled(&mut workspace, &ctx, &path, false),
+        SubCommand::Mv { path, to } => {
+            if ctx.no_save {
+                log::error!("mv moves the clone, it can't be combined with --no-save");
+                process::exit(1);
+            }
+            if let Err(e) = workspace.move_clone(&ctx, &path, &to) {
+                log::error!("{}", e);
+                process::exit(1);
+            }
+            if !ctx.dry_run {
+                persist(&mut workspace, &ctx);
+            }
+        }
+        SubCommand::Open {
+            path,
+            owner,
+            issues,
+            pulls,
+        } => {
+            let project = match workspace.find(&ctx, &path) {
+                Ok(project) => project,
+                Err(e) => {
+                    log::error!("{}", e);
+                    process::exit(1);
+                }
+            };
+            let page = match (owner, issues, pulls) {
+                (true, _, _) => WebPage::Owner,
+                (_, true, _) => WebPage::Issues,
+                (_, _, true) => WebPage::PullRequests,
+                _ => WebPage::Repository,
+            };
+            let url = project.provider.web_url(&project.path, page);
+            log::info!("Opening {}", url);
+            if let Err(e) = open_in_browser(&url) {
+                log::error!("Could not open {}: {}", url, e);
+                process::exit(1);
+            }
+        }
+        SubCommand::Verify { fix } => {
+            let mismatches = workspace.verify(&ctx, fix);
+            if mismatches == 0 {
+                log::info!("The remotes of all clones match the workspace");
+            } else if fix {
+                persist(&mut workspace, &ctx);
             } else {
-                log::info!(" - {}", project.path.display());
+                log::error!(
+                    "{} clones don't match the workspace, use --fix to update it",
+                    mismatches
+                );
+                process::exit(1);
+            }
+        }
+        SubCommand::Prune => {
+            let removed = workspace.prune(&ctx);
+            let action = if ctx.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            };
+            log::info!("{} {} repositories which are not cloned", action, removed);
+            if removed > 0 {
+                persist(&mut workspace, &ctx);
             }
-        }),
-        Opt::Build => workspace.build(),
-        Opt::Add { path, cmd } => {
-            workspace.add(&path, cmd).ok();
-            workspace.save();
         }
-        Opt::Remove { path, provider } => {
+        SubCommand::Freeze => workspace.freeze(&ctx),
+        SubCommand::Restore => match Lock::load(&ctx) {
+            Ok(lock) => conclude(workspace.restore(&ctx, &lock), &ctx),
+            Err(e) => {
+                log::error!("{}", e);
+                process::exit(1);
+            }
+        },
+        SubCommand::Graph { mermaid } => workspace.graph(if mermaid {
+            GraphFormat::Mermaid
+        } else {
+            GraphFormat::Dot
+        }),
+        SubCommand::Remove {
+            path,
+            provider,
+            delete_local,
+            force,
+        } => {
             if let Some(provider) = Provider::from(&provider) {
-                workspace.remove(&path, provider);
-                workspace.save();
+                let removed = workspace.remove(&path, provider);
+                persist(&mut workspace, &ctx);
+                // The clone stays as long as the workspace file refers to it
+                if let Some(project) = removed.filter(|_| delete_local && !ctx.no_save) {
+                    if let Err(e) = project.delete_local(&ctx, force) {
+                        log::error!("Could not delete {:?}: {}", project.path, e);
+                    }
+                }
             } else {
                 log::error!("Invalid provider: {}", provider);
             }
         }
-        Opt::Scan { path, dry_run } => {
-            workspace.scan(path).ok();
-            if !dry_run {
-                workspace.save();
+        SubCommand::Scan {
+            path,
+            depth,
+            patterns,
+            infer_cmd,
+        } => {
+            let mut state = State::load(&ctx);
+            workspace
+                .scan(&ctx, path, depth, patterns, infer_cmd, &mut state)
+                .ok();
+            if !ctx.dry_run {
+                if let Err(e) = state.save() {
+                    log::warn!("Could not write the state file: {}", e);
+                }
             }
+            persist(&mut workspace, &ctx);
         }
     }
 }
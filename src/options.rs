@@ -0,0 +1,269 @@
+//! Settings which are given on the command line or in the workspace file.
+
+use crate::project::Project;
+use crate::provider::Provider;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use structopt::StructOpt;
+
+/// Matches the text against a glob pattern, where `*` matches any sequence
+/// of characters (including `/`) and `?` matches a single character
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Splits a build command into program and arguments like a POSIX shell does,
+/// so quoted arguments stay together. Fails on an unterminated quote
+pub fn parse_cmd(cmd: &str) -> Result<Vec<String>, String> {
+    shell_words::split(cmd).map_err(|e| format!("Invalid command {:?}: {}", cmd, e))
+}
+
+/// Validates a build command given on the command line
+pub fn validate_cmd(cmd: String) -> Result<(), String> {
+    parse_cmd(&cmd).map(drop)
+}
+
+/// How a cloned repository is updated by pull and sync.
+/// Except for `Reset`, nothing is merged if the upstream has no new commits
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncStrategy {
+    /// `git fetch` followed by `git merge @{u}`
+    Pull,
+    /// `git fetch` followed by `git rebase @{u}`
+    Rebase,
+    /// `git fetch` followed by `git reset --hard @{u}`
+    Reset,
+    /// `git fetch` followed by `git merge --ff-only @{u}`
+    FfOnly,
+}
+
+impl std::str::FromStr for SyncStrategy {
+    type Err = String;
+
+    fn from_str(strategy: &str) -> Result<Self, Self::Err> {
+        match strategy {
+            "pull" => Ok(Self::Pull),
+            "rebase" => Ok(Self::Rebase),
+            "reset" => Ok(Self::Reset),
+            "ff-only" => Ok(Self::FfOnly),
+            _ => Err(format!("Unknown sync strategy {:?}", strategy)),
+        }
+    }
+}
+
+/// Where the repositories are cloned into, relative to the root
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// `<root>/<repo>`
+    #[default]
+    Flat,
+    /// `<root>/<owner>/<repo>`
+    OwnerRepo,
+    /// `<root>/<provider-host>/<owner>/<repo>`, also accepted as `nested`
+    #[serde(alias = "nested")]
+    ProviderOwnerRepo,
+}
+
+impl Layout {
+    pub(crate) fn is_flat(&self) -> bool {
+        *self == Self::Flat
+    }
+}
+
+/// Output format of the informational commands
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown format {:?}", format)),
+        }
+    }
+}
+
+/// Output format of the dependency graph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+/// Settings of a repository added with `add`
+#[derive(StructOpt, Debug, Clone, Default)]
+pub struct AddOptions {
+    #[structopt(long, validator = validate_cmd)]
+    /// Optional build command for the repository, arguments can be quoted like in a shell
+    pub(crate) cmd: Option<String>,
+    #[structopt(long, conflicts_with = "cmd")]
+    /// Detect the build command by the manifest of the repository, e.g. `cargo build` for a Cargo.toml
+    pub(crate) infer_cmd: bool,
+    #[structopt(long)]
+    /// Clone the repository via SSH, even if its remote uses HTTPS
+    pub(crate) ssh: bool,
+    #[structopt(long = "tag")]
+    /// Tag of the repository, can be repeated
+    pub(crate) tags: Vec<String>,
+    #[structopt(long)]
+    /// Remote the repository is added from, e.g. `upstream` of a fork. Defaults to origin
+    pub(crate) remote: Option<String>,
+}
+
+/// Restricts which directories `scan` considers, matched against the path relative
+/// to the scanned directory or the name of the directory, e.g. `node_modules`
+#[derive(StructOpt, Debug, Default)]
+pub struct ScanPatterns {
+    #[structopt(long)]
+    /// Only add repositories matching one of these glob patterns
+    include: Vec<String>,
+    #[structopt(long)]
+    /// Neither add nor descend into directories matching one of these glob patterns,
+    /// nor into the ones listed in a `.workspaceignore` of the scanned directory
+    pub(crate) exclude: Vec<String>,
+}
+
+impl ScanPatterns {
+    /// The patterns of a `.workspaceignore`, one per line like in a `.gitignore`.
+    /// Blank lines and comments are skipped, negations are not supported
+    pub(crate) fn parse_ignore(content: &str) -> Vec<String> {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                if line.starts_with('!') {
+                    log::warn!("Negated patterns are not supported, ignoring {:?}", line);
+                    return None;
+                }
+                // Patterns are relative to the scanned directory and only match directories anyway
+                Some(line.trim_matches('/').to_string())
+            })
+            .collect()
+    }
+
+    fn matches(patterns: &[String], path: &Path) -> bool {
+        let name = path.file_name().map(|name| name.to_string_lossy());
+        let path = path.to_string_lossy();
+        patterns.iter().any(|pattern| {
+            glob_match(pattern, &path)
+                || name
+                    .as_ref()
+                    .map_or(false, |name| glob_match(pattern, name))
+        })
+    }
+
+    pub(crate) fn includes(&self, path: &Path) -> bool {
+        self.include.is_empty() || Self::matches(&self.include, path)
+    }
+
+    pub(crate) fn excludes(&self, path: &Path) -> bool {
+        Self::matches(&self.exclude, path)
+    }
+}
+
+/// Restricts an operation to some of the repositories
+#[derive(StructOpt, Debug)]
+pub struct Filter {
+    #[structopt(long)]
+    /// Only repositories of this provider, e.g. `github` or the host of a self-hosted provider
+    provider: Option<Provider>,
+    #[structopt(long)]
+    /// Only repositories with this tag
+    tag: Option<String>,
+}
+
+impl Filter {
+    pub(crate) fn matches(&self, project: &Project) -> bool {
+        self.provider
+            .as_ref()
+            .map_or(true, |provider| *provider == project.provider)
+            && self
+                .tag
+                .as_ref()
+                .map_or(true, |tag| project.tags.contains(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_patterns() {
+        let patterns = ScanPatterns {
+            include: vec![String::from("team-*")],
+            exclude: vec![String::from("node_modules"), String::from("*/.cache")],
+        };
+
+        assert!(patterns.excludes(Path::new("web/node_modules")));
+        assert!(patterns.excludes(Path::new("web/.cache")));
+        assert!(!patterns.excludes(Path::new("web")));
+        assert!(patterns.includes(Path::new("team-api")));
+        assert!(!patterns.includes(Path::new("other")));
+        assert!(ScanPatterns::default().includes(Path::new("other")));
+    }
+
+    #[test]
+    fn workspaceignore_patterns() {
+        let ignore = "# generated\nnode_modules/\n\n/vendor\n  target  \n!keep\n";
+
+        assert_eq!(
+            ScanPatterns::parse_ignore(ignore),
+            ["node_modules", "vendor", "target"]
+        );
+    }
+
+    #[test]
+    fn quoted_cmd_arguments() {
+        assert_eq!(
+            parse_cmd(r#"cargo build  --features "a b" --message 'it''s' a\ b """#).unwrap(),
+            [
+                "cargo",
+                "build",
+                "--features",
+                "a b",
+                "--message",
+                "its",
+                "a b",
+                ""
+            ]
+        );
+        assert_eq!(
+            parse_cmd(r#"echo "say \"hi\"""#).unwrap(),
+            ["echo", r#"say "hi""#]
+        );
+        assert!(parse_cmd("  ").unwrap().is_empty());
+        assert!(parse_cmd(r#"echo "unterminated"#).is_err());
+        assert!(parse_cmd("echo 'unterminated").is_err());
+    }
+}
@@ -0,0 +1,1028 @@
+//! A repository of the workspace file and the [`Git`] operations on it.
+
+use crate::git::{
+    execute, execute_unless_dry_run, git_checked, git_stdout, git_unless_dry_run, Error, Repository,
+};
+use crate::options::{Layout, SyncStrategy};
+use crate::provider::{is_ssh_url, Provider};
+use crate::report::{json_string, Outcome};
+use crate::workspace::confirm;
+use crate::{dir_size, expand_env, format_size, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Resolves a program given by a relative path, e.g. `./scripts/build.sh`, against the directory
+/// it runs in. `Command` resolves it against either directory depending on the platform.
+/// Programs without a directory, like `cargo`, are still looked up in the `PATH`
+pub(crate) fn resolve_program(program: &str, dir: &Path, dry_run: bool) -> Result<PathBuf, Error> {
+    let path = Path::new(program);
+    if path.is_absolute() || path.components().count() < 2 {
+        return Ok(path.to_path_buf());
+    }
+
+    let resolved = dir.join(path);
+    if dry_run {
+        return Ok(resolved);
+    }
+    match resolved.metadata() {
+        Ok(metadata) if metadata.is_file() => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                if metadata.permissions().mode() & 0o111 == 0 {
+                    return Err(Error::Failed(format!(
+                        "{} in {:?} is not executable, run `chmod +x {}`",
+                        program, dir, program
+                    )));
+                }
+            }
+        }
+        _ => {
+            return Err(Error::Failed(format!(
+                "{} does not exist in {:?}",
+                program, dir
+            )))
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Build command of the repository in the directory, detected by the manifest at its root
+pub(crate) fn detect_build_cmd(dir: &Path) -> Option<Vec<String>> {
+    const MANIFESTS: &[(&str, &[&str])] = &[
+        ("Cargo.toml", &["cargo", "build"]),
+        ("package.json", &["npm", "ci"]),
+        ("Makefile", &["make"]),
+    ];
+
+    MANIFESTS
+        .iter()
+        .find(|(manifest, _)| dir.join(manifest).is_file())
+        .map(|(_, cmd)| cmd.iter().map(|arg| arg.to_string()).collect())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Project {
+    pub provider: Provider,
+    pub path: PathBuf,
+    /// Build command, the `default_cmd` of the workspace is used if it is empty
+    #[serde(default)]
+    pub cmd: Vec<String>,
+    /// Whether the project is not built, even if the workspace has a `default_cmd`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_build: bool,
+    /// Remote url the project was added from, only set if the provider would build another one,
+    /// e.g. because of a port. Cloned from instead of the url built from provider and path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Clone directory relative to the root, or absolute, instead of the one of the layout.
+    /// Recorded by `add` for repositories outside of the layout and to resolve name collisions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// Overrides the `--strategy` used by pull and sync
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_strategy: Option<SyncStrategy>,
+    /// Branch which is cloned and checked out before pulling instead of the default branch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Tag or commit the clone is checked out at and kept at instead of pulling.
+    /// A branch name behaves like `branch`
+    #[serde(default, rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Whether the project takes part in bulk operations, disabled ones are skipped
+    #[serde(default = "enabled", skip_serializing_if = "Clone::clone")]
+    pub enabled: bool,
+    /// Whether the project is cloned via SSH instead of HTTPS
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ssh: bool,
+    /// Remote the project was added from, pulled and fetched from, `origin` if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+    /// Whether submodules are cloned and updated after pulling
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub submodules: bool,
+    /// Whether the project is cloned as a bare mirror of all refs, e.g. for backups.
+    /// Mirrors are updated with `git remote update`, they have no working tree to build
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub mirror: bool,
+    /// Paths of the projects this project depends on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<PathBuf>,
+    /// Tags to operate on a group of projects, e.g. `frontend`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Environment variables of the build command. Tables have to be serialized last
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+/// Projects are enabled unless the workspace file says otherwise
+fn enabled() -> bool {
+    true
+}
+
+pub trait Git {
+    type Output;
+
+    fn git_pull(&self, ctx: &Context) -> Self::Output;
+    fn git_clone(&self, ctx: &Context) -> Self::Output;
+    fn git_fetch(&self, ctx: &Context) -> Self::Output;
+    fn git_sync(&self, ctx: &Context) -> Self::Output;
+}
+
+impl Project {
+    pub(crate) fn new(provider: Provider, path: PathBuf) -> Self {
+        Self {
+            provider,
+            path,
+            cmd: Vec::new(),
+            no_build: false,
+            url: None,
+            dir: None,
+            sync_strategy: None,
+            branch: None,
+            git_ref: None,
+            enabled: true,
+            ssh: false,
+            remote: None,
+            submodules: false,
+            mirror: false,
+            depends_on: Vec::new(),
+            tags: Vec::new(),
+            env: BTreeMap::new(),
+        }
+    }
+
+    /// The build command of the project, otherwise the `default_cmd` of the workspace
+    pub(crate) fn build_cmd<'a>(&'a self, default_cmd: &'a [String]) -> &'a [String] {
+        match (self.no_build, self.cmd.is_empty()) {
+            (true, _) => &[],
+            (false, true) => default_cmd,
+            (false, false) => &self.cmd,
+        }
+    }
+
+    /// The url the project was added from, unless `--ssh` requires another one
+    pub(crate) fn clone_url(&self, ctx: &Context) -> String {
+        match &self.url {
+            Some(url) if !ctx.ssh || is_ssh_url(url) => url.clone(),
+            _ => self.provider.get_clone_url(&self.path, self.ssh || ctx.ssh),
+        }
+    }
+
+    pub(crate) fn get_absolute_path(&self, ctx: &Context) -> PathBuf {
+        ctx.root.join(self.get_folder(ctx.layout))
+    }
+
+    /// The clone directory relative to the root as determined by the layout, unless overridden
+    pub(crate) fn get_folder(&self, layout: Layout) -> PathBuf {
+        if let Some(dir) = &self.dir {
+            return PathBuf::from(expand_env(&dir.to_string_lossy()));
+        }
+
+        match layout {
+            Layout::Flat => {
+                PathBuf::from(self.get_path().file_stem().expect("Could not get folder"))
+            }
+            Layout::OwnerRepo => self.get_path().to_path_buf(),
+            Layout::ProviderOwnerRepo => Path::new(self.provider.host()).join(self.get_path()),
+        }
+    }
+
+    pub(crate) fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The current branch of the clone, `(detached)` if HEAD is detached
+    pub(crate) fn current_branch(&self, ctx: &Context) -> Option<String> {
+        let repo = self.get_repository(ctx);
+        if !repo.exists_local() {
+            return None;
+        }
+
+        repo.current_branch().map(|branch| {
+            if branch == "HEAD" {
+                String::from("(detached)")
+            } else {
+                branch
+            }
+        })
+    }
+
+    /// Whether the clone is on another branch than the recorded one
+    pub(crate) fn is_off_branch(&self, current_branch: Option<&str>) -> bool {
+        match (&self.branch, current_branch) {
+            (Some(expected), Some(current)) => expected != current,
+            _ => false,
+        }
+    }
+
+    /// Whether the operation is skipped because it needs a working tree, which mirrors lack
+    fn skips_mirror(&self, operation: &str) -> bool {
+        if self.mirror {
+            log::info!("~ {:?} is a mirror, skipping {}", self.path, operation);
+        }
+
+        self.mirror
+    }
+
+    pub fn is_cloned(&self, ctx: &Context) -> bool {
+        self.get_repository(ctx).exists_local()
+    }
+
+    pub(crate) fn get_repository<'a>(&'a self, ctx: &'a Context) -> Repository<'a> {
+        Repository {
+            local_path: self.get_absolute_path(ctx),
+            git_path: self.get_path(),
+            remote: ctx.remote.clone().or_else(|| self.remote.clone()),
+            submodules: self.submodules,
+            mirror: self.mirror,
+            ctx,
+        }
+    }
+
+    /// With `--strict`, fails if the url of the remote is not the one the workspace file
+    /// expects, e.g. because the remote was pointed to another host
+    fn check_remote_url(&self, ctx: &Context, repo: &Repository<'_>) -> Result<(), Error> {
+        if !ctx.strict {
+            return Ok(());
+        }
+
+        let remote = self.remote.as_deref().unwrap_or("origin");
+        let url = git_stdout(ctx, &["remote", "get-url", remote], Some(&repo.local_path))
+            .ok_or_else(|| {
+                Error::Failed(format!("{} has no remote {}", self.path.display(), remote))
+            })?;
+        let normalize = |url: &str| url.trim_end_matches(".git").to_lowercase();
+        let custom_url = self.provider.custom_url(&self.path, &url);
+        if custom_url.as_deref().map(normalize) == self.url.as_deref().map(normalize) {
+            return Ok(());
+        }
+
+        Err(Error::Failed(format!(
+            "{} points to {}, expected {}",
+            remote,
+            url,
+            self.clone_url(ctx)
+        )))
+    }
+
+    /// Fails if the clone directory contains a clone of another repository,
+    /// e.g. because two projects share the same directory
+    fn check_origin(&self, repo: &Repository<'_>) -> Result<(), Error> {
+        let normalize = |path: &Path| path.to_string_lossy().to_lowercase();
+        match repo.remote_path(self.remote.as_deref().unwrap_or("origin")) {
+            Some((_, path)) if normalize(&path) != normalize(repo.git_path) => {
+                Err(Error::Failed(format!(
+                    "{} is a clone of {}, not of {}",
+                    repo.local_path.display(),
+                    path.display(),
+                    repo.git_path.display()
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn build(&self, ctx: &Context, default_cmd: &[String]) -> Result<(), Error> {
+        let (program, args) = match self.build_cmd(default_cmd).split_first() {
+            Some(cmd) => cmd,
+            None => return Ok(()),
+        };
+        if self.skips_mirror("build") {
+            return Ok(());
+        }
+        let repo = self.get_repository(ctx);
+        if repo.exists_local() {
+            self.check_origin(&repo)?;
+        }
+        let dir = self.get_absolute_path(ctx);
+        let mut command = Command::new(resolve_program(&expand_env(program), &dir, ctx.dry_run)?);
+        command
+            .current_dir(&dir)
+            .args(args.iter().map(|arg| expand_env(arg)))
+            .envs(self.env.iter().map(|(key, value)| (key, expand_env(value))));
+        if !ctx.dry_run {
+            log::info!("- Build {:?}...", self.path);
+        }
+
+        self.execute_logged(&mut command, ctx.dry_run)
+    }
+
+    /// Runs the command in the clone directory, the project has to be cloned
+    pub(crate) fn exec(&self, ctx: &Context, command: &[String]) -> Result<(), Error> {
+        let repo = self.get_repository(ctx);
+        if !repo.exists_local() {
+            log::info!("~ {:?} is not cloned yet", repo.git_path);
+            return Ok(());
+        }
+
+        let (program, args) = match command.split_first() {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        if !ctx.dry_run {
+            log::info!("- Exec in {:?}...", self.path);
+        }
+
+        self.execute_logged(
+            Command::new(program)
+                .current_dir(&repo.local_path)
+                .args(args),
+            ctx.dry_run,
+        )
+    }
+
+    /// Executes the command and logs its output at once afterwards,
+    /// so the output of commands running in parallel doesn't interleave
+    fn execute_logged(&self, command: &mut Command, dry_run: bool) -> Result<(), Error> {
+        if dry_run {
+            return execute_unless_dry_run(command, true);
+        }
+
+        let output = execute(command)?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !stdout.trim().is_empty() {
+            log::info!("Output of {}:\n{}", self.path.display(), stdout.trim_end());
+        }
+
+        Ok(())
+    }
+}
+
+impl Project {
+    /// Pushes the current branch if it has commits which are not pushed yet.
+    /// Branches without upstream are only pushed with `set_upstream`.
+    /// In a dry run git only reports what would be pushed.
+    pub(crate) fn git_push(&self, ctx: &Context, set_upstream: bool) -> Result<Outcome, Error> {
+        let repo = self.get_repository(ctx);
+        if !repo.exists_local() {
+            log::info!("~ {:?} is not cloned yet", repo.git_path);
+            return Ok(Outcome::Skipped);
+        }
+        if self.skips_mirror("push") {
+            return Ok(Outcome::Skipped);
+        }
+
+        let mut args = Vec::new();
+        if ctx.dry_run {
+            args.push("--dry-run");
+        }
+
+        let branch = match repo.current_branch().filter(|branch| branch != "HEAD") {
+            Some(branch) => branch,
+            None => {
+                log::info!("~ {:?} has a detached HEAD, skipping push", repo.git_path);
+                return Ok(Outcome::Skipped);
+            }
+        };
+        // A configured remote is pushed to instead of the upstream of the branch
+        let remote = repo.remote.as_deref();
+        let upstream = remote.map_or(String::from("@{u}"), |remote| {
+            format!("{}/{}", remote, branch)
+        });
+        match repo.ahead_behind(&upstream) {
+            Some((0, _)) => {
+                log::info!("~ {:?} has nothing to push", repo.git_path);
+                Ok(Outcome::UpToDate)
+            }
+            Some(_) => {
+                if let Some(remote) = remote {
+                    args.extend(&[remote, &branch]);
+                }
+                self.provider.git_push(&repo, &args)?;
+                Ok(Outcome::Pushed)
+            }
+            None if set_upstream => {
+                args.extend(&["--set-upstream", remote.unwrap_or("origin"), &branch]);
+                self.provider.git_push(&repo, &args)?;
+                Ok(Outcome::Pushed)
+            }
+            None => {
+                log::info!(
+                    "~ {:?} has no upstream for {}, use --set-upstream",
+                    repo.git_path,
+                    branch
+                );
+                Ok(Outcome::Skipped)
+            }
+        }
+    }
+
+    /// Deletes the clone directory, a dirty working tree only if confirmed or forced
+    pub fn delete_local(&self, ctx: &Context, force: bool) -> std::io::Result<()> {
+        use std::fs;
+
+        let repo = self.get_repository(ctx);
+        if !repo.local_path.join(".git").exists() {
+            log::warn!(
+                "{:?} is not a git repository, not deleting it",
+                repo.local_path
+            );
+            return Ok(());
+        }
+
+        if repo.is_dirty()
+            && !force
+            && !ctx.yes
+            && !confirm(&format!(
+                "{} has uncommitted changes. Delete it anyway?",
+                repo.local_path.display()
+            ))
+        {
+            log::info!("~ Keeping {:?}", repo.local_path);
+            return Ok(());
+        }
+
+        if ctx.dry_run {
+            log::info!("  Would delete {:?}", repo.local_path);
+            return Ok(());
+        }
+        fs::remove_dir_all(&repo.local_path)?;
+        log::info!("- Deleted {:?}", repo.local_path);
+
+        Ok(())
+    }
+
+    /// Whether `clean` would remove any files
+    pub fn would_clean(&self, ctx: &Context, ignored: bool) -> bool {
+        let repo = self.get_repository(ctx);
+        let args: &[&str] = if ignored {
+            &["clean", "-d", "-n", "-x"]
+        } else {
+            &["clean", "-d", "-n"]
+        };
+        repo.exists_local()
+            && git_stdout(ctx, args, Some(&repo.local_path))
+                .map_or(false, |files| !files.is_empty())
+    }
+
+    /// Removes untracked files, or only reports them unless `force` is set
+    pub(crate) fn git_clean(&self, ctx: &Context, force: bool, ignored: bool) -> Result<(), Error> {
+        let repo = self.get_repository(ctx);
+        if !repo.exists_local() {
+            log::info!("~ {:?} is not cloned yet", repo.git_path);
+            return Ok(());
+        }
+        if self.skips_mirror("clean") {
+            return Ok(());
+        }
+        self.check_origin(&repo)?;
+
+        let mut args = vec!["clean", "-d"];
+        args.push(if force && !ctx.dry_run { "-f" } else { "-n" });
+        if ignored {
+            args.push("-x");
+        }
+        let output = git_checked(ctx, &args, &repo.local_path)?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // git reports every file as `Removing <file>` or `Would remove <file>`
+        let files: Vec<&str> = stdout
+            .lines()
+            .filter_map(|line| {
+                line.strip_prefix("Removing ")
+                    .or_else(|| line.strip_prefix("Would remove "))
+            })
+            .collect();
+        if files.is_empty() {
+            log::info!("~ {:?} is clean", repo.git_path);
+        } else {
+            let action = if args.contains(&"-f") {
+                "Removed"
+            } else {
+                "Would remove"
+            };
+            log::info!("- {} {} files of {:?}:", action, files.len(), repo.git_path);
+            files
+                .iter()
+                .for_each(|file| log::info!("  {}: {}", repo.git_path.display(), file));
+        }
+
+        Ok(())
+    }
+
+    /// Runs `git gc` and logs how much space it reclaimed
+    pub(crate) fn git_gc(&self, ctx: &Context, aggressive: bool) -> Result<Outcome, Error> {
+        let repo = self.get_repository(ctx);
+        if !repo.exists_local() {
+            log::info!("~ {:?} is not cloned yet", repo.git_path);
+            return Ok(Outcome::Skipped);
+        }
+        self.check_origin(&repo)?;
+
+        log::info!("- Collect garbage in {:?}...", repo.git_path);
+        let before = repo.object_size();
+        let mut args = vec!["gc", "--quiet"];
+        if aggressive {
+            args.push("--aggressive");
+        }
+        git_unless_dry_run(ctx, &args, &repo.local_path)?;
+        if let (Some(before), Some(after)) = (before, repo.object_size()) {
+            if before > after {
+                log::info!(
+                    "- Reclaimed {} in {:?}, {} left",
+                    format_size(before - after),
+                    repo.git_path,
+                    format_size(after)
+                );
+            }
+        }
+
+        Ok(Outcome::Done)
+    }
+
+    /// Discards all local commits and changes of the current branch by resetting it to its upstream.
+    /// With `clean` untracked files are removed as well
+    pub(crate) fn git_reset(&self, ctx: &Context, clean: bool) -> Result<Outcome, Error> {
+        let repo = self.get_repository(ctx);
+        if !repo.exists_local() {
+            log::info!("~ {:?} is not cloned yet", repo.git_path);
+            return Ok(Outcome::Skipped);
+        }
+        if self.skips_mirror("reset") {
+            return Ok(Outcome::Skipped);
+        }
+        self.check_origin(&repo)?;
+
+        if ctx.dry_run {
+            let upstream = match repo.remote.as_deref().zip(repo.current_branch()) {
+                Some((remote, branch)) => format!("{}/{}", remote, branch),
+                None => String::from("@{u}"),
+            };
+            let mut discarded = Vec::new();
+            if let Some((ahead, _)) = repo.ahead_behind(&upstream).filter(|(a, _)| *a > 0) {
+                discarded.push(format!("{} local commits", ahead));
+            }
+            if repo.has_changes() {
+                discarded.push(String::from("uncommitted changes"));
+            }
+            if clean && repo.has_untracked_files() {
+                discarded.push(String::from("untracked files"));
+            }
+            if discarded.is_empty() {
+                log::info!("- Would reset {:?}", repo.git_path);
+            } else {
+                log::info!(
+                    "- Would reset {:?}, discarding {}",
+                    repo.git_path,
+                    discarded.join(", ")
+                );
+            }
+            return Ok(Outcome::Done);
+        }
+
+        self.provider.git_pull(&repo, SyncStrategy::Reset)?;
+        if clean {
+            log::info!("- Clean {:?}...", repo.git_path);
+            git_checked(ctx, &["clean", "-fd"], &repo.local_path)?;
+        }
+
+        Ok(Outcome::Done)
+    }
+
+    /// Checks out the branch if it exists locally or on origin, skips the project otherwise
+    pub(crate) fn git_checkout(&self, ctx: &Context, branch: &str) -> Result<Outcome, Error> {
+        let repo = self.get_repository(ctx);
+        if !repo.exists_local() {
+            log::info!("~ {:?} is not cloned yet", repo.git_path);
+            return Ok(Outcome::Skipped);
+        }
+        if self.skips_mirror("checkout") {
+            return Ok(Outcome::Skipped);
+        }
+
+        if repo.current_branch().as_deref() == Some(branch) {
+            log::info!("~ {:?} is already on {}", repo.git_path, branch);
+            return Ok(Outcome::Done);
+        }
+
+        if !repo.has_revision(&format!("refs/heads/{}", branch))
+            && !repo.has_revision(&format!("refs/remotes/origin/{}", branch))
+        {
+            log::warn!(
+                "~ {:?} has no branch {}, neither locally nor on origin",
+                repo.git_path,
+                branch
+            );
+            return Ok(Outcome::Skipped);
+        }
+
+        self.provider.git_checkout(&repo, branch)?;
+        Ok(Outcome::Done)
+    }
+
+    /// Whether the branch to pull has an upstream, pulling one without fails with a cryptic error.
+    /// With `--set-upstream` the branch of the same name on origin is set up as upstream
+    fn has_upstream(&self, ctx: &Context, repo: &Repository) -> Result<bool, Error> {
+        // The remote branch is pulled explicitly
+        if repo.remote.is_some() {
+            return Ok(true);
+        }
+        let branch = match self.tracked_branch(repo).or_else(|| repo.current_branch()) {
+            Some(branch) => branch,
+            None => return Ok(true),
+        };
+        // A branch which does not exist yet is created tracking origin by the checkout
+        if !repo.has_revision(&format!("refs/heads/{}", branch)) || repo.upstream(&branch).is_some()
+        {
+            return Ok(true);
+        }
+
+        let origin = format!("origin/{}", branch);
+        if ctx.set_upstream && repo.has_revision(&origin) {
+            log::info!(
+                "- Set the upstream of {} in {:?} to {}",
+                branch,
+                repo.git_path,
+                origin
+            );
+            git_unless_dry_run(
+                ctx,
+                &["branch", &format!("--set-upstream-to={}", origin), &branch],
+                &repo.local_path,
+            )?;
+            return Ok(true);
+        }
+
+        log::warn!(
+            "~ {:?} has no upstream configured for branch {}, skipping pull{}",
+            repo.git_path,
+            branch,
+            if ctx.set_upstream {
+                format!(", {} does not exist", origin)
+            } else {
+                String::from(", use --set-upstream to track origin")
+            }
+        );
+
+        Ok(false)
+    }
+
+    /// The branch which is checked out before pulling, the `branch` or the `ref` if it is one
+    fn tracked_branch(&self, repo: &Repository) -> Option<String> {
+        self.branch
+            .clone()
+            .or_else(|| self.git_ref.clone().filter(|name| repo.is_branch(name)))
+    }
+
+    /// The `ref` unless it is a branch, i.e. a tag or commit the clone is kept at
+    fn pinned_ref(&self, repo: &Repository) -> Option<&str> {
+        self.git_ref.as_deref().filter(|name| !repo.is_branch(name))
+    }
+
+    /// Checks out the tag or commit, fetching first if it is unknown
+    fn checkout_ref(&self, repo: &Repository, name: &str) -> Result<Outcome, Error> {
+        let commit = || {
+            git_stdout(
+                repo.ctx,
+                &[
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{}^{{commit}}", name),
+                ],
+                Some(&repo.local_path),
+            )
+        };
+        let commit = match commit() {
+            Some(commit) => commit,
+            None => {
+                self.provider.git_fetch(repo, &["--tags"])?;
+                commit().ok_or_else(|| {
+                    Error::Failed(format!("{} does not exist in {:?}", name, repo.git_path))
+                })?
+            }
+        };
+        if repo.head().as_deref() == Some(commit.as_str()) {
+            log::info!("~ {:?} is at {}", repo.git_path, name);
+            return Ok(Outcome::UpToDate);
+        }
+        self.provider.git_checkout(repo, name)?;
+
+        Ok(Outcome::Done)
+    }
+
+    /// Checks out the pinned branch if necessary and pulls it
+    fn checkout_and_pull(&self, repo: &Repository, strategy: SyncStrategy) -> Result<(), Error> {
+        if let Some(branch) = self.tracked_branch(repo) {
+            if repo.current_branch().as_ref() != Some(&branch) {
+                self.provider.git_checkout(repo, &branch)?;
+            }
+        }
+        self.provider.git_pull(repo, strategy)?;
+        if repo.submodules && repo.local_path.join(".gitmodules").exists() {
+            self.provider.git_update_submodules(repo)?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks out the commit recorded by `freeze`, fetching first if the commit is unknown
+    pub(crate) fn restore(&self, ctx: &Context, commit: Option<&str>) -> Result<Outcome, Error> {
+        let repo = self.get_repository(ctx);
+        let commit = match commit {
+            Some(commit) if repo.exists_local() => commit,
+            Some(_) => {
+                log::info!("~ {:?} is not cloned yet", repo.git_path);
+                return Ok(Outcome::Skipped);
+            }
+            None => {
+                log::warn!("~ {:?} has no commit in the lock file", repo.git_path);
+                return Ok(Outcome::Skipped);
+            }
+        };
+
+        self.check_origin(&repo)?;
+        if repo.head().as_deref() == Some(commit) {
+            log::info!("~ {:?} is already at {}", repo.git_path, commit);
+            return Ok(Outcome::UpToDate);
+        }
+        if !repo.has_revision(&format!("{}^{{commit}}", commit)) {
+            self.provider.git_fetch(&repo, &[])?;
+        }
+        self.provider.git_checkout(&repo, commit)?;
+
+        Ok(Outcome::Done)
+    }
+}
+
+impl Git for Project {
+    type Output = Result<Outcome, Error>;
+
+    fn git_pull(&self, ctx: &Context) -> Self::Output {
+        let repo = self.get_repository(ctx);
+        if !repo.exists_local() {
+            log::info!("~ {:?} is not cloned yet", repo.git_path);
+            return Ok(Outcome::Skipped);
+        }
+        self.check_remote_url(ctx, &repo)?;
+        if self.mirror {
+            self.check_origin(&repo)?;
+            let refs = || git_stdout(ctx, &["show-ref"], Some(&repo.local_path));
+            let before = refs();
+            self.provider.git_update_mirror(&repo)?;
+            return Ok(if ctx.dry_run || refs() != before {
+                Outcome::Pulled
+            } else {
+                Outcome::UpToDate
+            });
+        }
+        if let Some(name) = self.pinned_ref(&repo) {
+            self.check_origin(&repo)?;
+            return self.checkout_ref(&repo, name);
+        }
+        if repo.is_detached() && !ctx.force_pull_detached {
+            log::info!(
+                "~ {:?} is pinned (detached HEAD), skipping pull",
+                repo.git_path
+            );
+            return Ok(Outcome::Skipped);
+        }
+
+        let strategy = if ctx.rebase {
+            SyncStrategy::Rebase
+        } else {
+            self.sync_strategy.unwrap_or(ctx.sync_strategy)
+        };
+        // A reset discards the changes anyway
+        let dirty = strategy != SyncStrategy::Reset && !ctx.force_pull_dirty && repo.has_changes();
+        if dirty && !ctx.stash {
+            log::warn!(
+                "~ {:?} has uncommitted changes, skipping pull",
+                repo.git_path
+            );
+            return Ok(Outcome::Skipped);
+        }
+
+        self.check_origin(&repo)?;
+        if !self.has_upstream(ctx, &repo)? {
+            return Ok(Outcome::Skipped);
+        }
+        let head = repo.head();
+        if dirty {
+            log::info!("- Stash the changes of {:?}...", repo.git_path);
+            git_unless_dry_run(
+                ctx,
+                &["stash", "push", "-m", "workspace: stashed before pull"],
+                &repo.local_path,
+            )?;
+        }
+        let pulled = self.checkout_and_pull(&repo, strategy);
+        if dirty {
+            // A conflicting pop leaves the stash in place, so nothing is lost
+            if let Err(e) = git_unless_dry_run(ctx, &["stash", "pop"], &repo.local_path) {
+                log::warn!(
+                    "~ The stashed changes of {:?} conflict with the pulled ones, \
+                     they are kept in the stash: {}",
+                    repo.local_path,
+                    e
+                );
+            }
+        }
+        pulled?;
+
+        // A dry run does not move HEAD, but would have pulled
+        if ctx.dry_run {
+            return Ok(Outcome::Pulled);
+        }
+        match (head, repo.head()) {
+            (Some(old), Some(new)) if old != new => {
+                let commits = git_stdout(
+                    ctx,
+                    &["rev-list", "--count", &format!("{}..{}", old, new)],
+                    Some(&repo.local_path),
+                );
+                log::info!(
+                    "~ {:?} updated {:.7}..{:.7} ({} new commits)",
+                    repo.git_path,
+                    old,
+                    new,
+                    commits.as_deref().unwrap_or("?")
+                );
+                Ok(Outcome::Pulled)
+            }
+            (old, new) if old != new => Ok(Outcome::Pulled),
+            _ => Ok(Outcome::UpToDate),
+        }
+    }
+
+    fn git_clone(&self, ctx: &Context) -> Self::Output {
+        let repo = self.get_repository(ctx);
+        if !repo.exists_local() {
+            self.provider
+                .git_clone(&repo, &self.clone_url(ctx), self.branch.as_deref())?;
+            if let Some(name) = self.git_ref.as_deref().filter(|_| !ctx.dry_run) {
+                match self.pinned_ref(&repo) {
+                    Some(name) => {
+                        self.checkout_ref(&repo, name)?;
+                    }
+                    None if repo.current_branch().as_deref() != Some(name) => {
+                        self.provider.git_checkout(&repo, name)?;
+                    }
+                    None => {}
+                }
+            }
+            Ok(Outcome::Cloned)
+        } else {
+            self.check_origin(&repo)?;
+            log::info!("~ {:?} is already cloned", repo.git_path);
+            Ok(Outcome::UpToDate)
+        }
+    }
+
+    fn git_fetch(&self, ctx: &Context) -> Self::Output {
+        let repo = self.get_repository(ctx);
+        if repo.exists_local() {
+            self.check_origin(&repo)?;
+            self.check_remote_url(ctx, &repo)?;
+            // git refuses to unshallow a complete repository
+            let unshallow = ctx.unshallow && repo.is_shallow();
+            if ctx.unshallow && !unshallow {
+                log::debug!("~ {:?} is not shallow", repo.git_path);
+            }
+            let deepen = ctx.deepen.map(|depth| format!("--deepen={}", depth));
+            let flags = [
+                (ctx.prune, "--prune"),
+                (ctx.tags, "--tags"),
+                (unshallow, "--unshallow"),
+            ];
+            let flags: Vec<&str> = flags
+                .iter()
+                .filter(|(enabled, _)| *enabled)
+                .map(|(_, flag)| *flag)
+                .chain(deepen.as_deref())
+                .collect();
+            self.provider.git_fetch(&repo, &flags)?;
+            Ok(Outcome::Fetched)
+        } else {
+            log::info!("~ {:?} is not cloned yet", repo.git_path);
+            Ok(Outcome::Skipped)
+        }
+    }
+
+    fn git_sync(&self, ctx: &Context) -> Self::Output {
+        if self.is_cloned(ctx) {
+            self.git_pull(ctx)
+        } else {
+            self.git_clone(ctx)
+        }
+    }
+}
+
+/// Overall state of a project as reported by `health`
+pub(crate) struct Health {
+    pub(crate) path: String,
+    pub(crate) cloned: bool,
+    pub(crate) dirty: bool,
+    pub(crate) ahead_behind: Option<(u32, u32)>,
+    pub(crate) reachable: bool,
+    pub(crate) size: u64,
+}
+
+impl Health {
+    pub(crate) fn check(project: &Project, ctx: &Context) -> Self {
+        let repo = project.get_repository(ctx);
+        let path = project.path.display().to_string();
+        if !repo.exists_local() {
+            return Self {
+                path,
+                cloned: false,
+                dirty: false,
+                ahead_behind: None,
+                reachable: false,
+                size: 0,
+            };
+        }
+
+        Self {
+            path,
+            cloned: true,
+            dirty: repo.is_dirty(),
+            ahead_behind: repo.ahead_behind("@{u}"),
+            reachable: repo.is_remote_reachable(),
+            size: dir_size(&repo.local_path),
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        let (ahead, behind) = self
+            .ahead_behind
+            .map_or((String::from("null"), String::from("null")), |(a, b)| {
+                (a.to_string(), b.to_string())
+            });
+
+        format!(
+            "{{\"path\":{},\"cloned\":{},\"dirty\":{},\"ahead\":{},\"behind\":{},\"reachable\":{},\"size\":{}}}",
+            json_string(&self.path),
+            self.cloned,
+            self.dirty,
+            ahead,
+            behind,
+            self.reachable,
+            self.size
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_cmd;
+
+    fn context(layout: Layout) -> Context {
+        let file = Path::new("workspace.toml");
+        let mut ctx = Context::new(file, Some(Path::new("/workspace"))).unwrap();
+        ctx.layout = layout;
+
+        ctx
+    }
+
+    fn project() -> Project {
+        Project::new(Provider::Github, PathBuf::from("owner/repo"))
+    }
+
+    #[test]
+    fn flat_layout() {
+        let ctx = context(Layout::Flat);
+
+        assert_eq!(
+            project().get_absolute_path(&ctx),
+            PathBuf::from("/workspace/repo")
+        );
+    }
+
+    #[test]
+    fn owner_repo_layout() {
+        let ctx = context(Layout::OwnerRepo);
+
+        assert_eq!(
+            project().get_absolute_path(&ctx),
+            PathBuf::from("/workspace/owner/repo")
+        );
+    }
+
+    #[test]
+    fn provider_owner_repo_layout() {
+        let ctx = context(Layout::ProviderOwnerRepo);
+
+        assert_eq!(
+            project().get_absolute_path(&ctx),
+            PathBuf::from("/workspace/github.com/owner/repo")
+        );
+    }
+
+    #[test]
+    fn quoted_cmd_round_trip() {
+        let project = Project {
+            cmd: parse_cmd(r#"make FLAGS="-O2 -g""#).unwrap(),
+            ..Project::new(Provider::Github, PathBuf::from("owner/repo"))
+        };
+        let project: Project = toml::from_str(&toml::to_string(&project).unwrap()).unwrap();
+
+        assert_eq!(project.cmd, ["make", "FLAGS=-O2 -g"]);
+    }
+}
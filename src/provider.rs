@@ -0,0 +1,751 @@
+//! The hosts repositories are cloned from and their urls.
+
+use crate::git::{
+    execute_unless_dry_run, git_checked, git_command, git_unless_dry_run, Error, Repository,
+};
+use crate::options::SyncStrategy;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Provider {
+    Github,
+    Gitlab,
+    Bitbucket,
+    /// A self-hosted provider, e.g. a company GitLab
+    Custom {
+        host: String,
+    },
+    /// Repositories in a local directory, e.g. a mirror, stored by its `file://` url
+    Local {
+        url: String,
+    },
+}
+
+/// The URLs of a git hosting provider. A new provider implements this trait,
+/// gets a variant in `Provider` and a name in `Provider::from`
+trait GitProvider {
+    /// Host of the provider, e.g. `github.com`
+    fn host(&self) -> &str;
+
+    /// Base url of the web interface, e.g. `https://github.com`
+    fn base_url(&self) -> String {
+        format!("https://{}", self.host())
+    }
+
+    /// Url to clone the repository at the path via HTTPS
+    fn clone_url(&self, path: &Path) -> String {
+        format!("{}/{}", self.base_url(), path.display())
+    }
+
+    /// Url to clone the repository at the path via SSH
+    fn ssh_clone_url(&self, path: &Path) -> String {
+        format!("git@{}:{}.git", self.host(), path.display())
+    }
+
+    /// User name which goes along with an access token when authenticating via HTTPS
+    fn token_user(&self) -> &str {
+        "oauth2"
+    }
+
+    /// Path of the page below the web url of a repository, e.g. `issues`
+    fn page(&self, page: WebPage) -> &str {
+        match page {
+            WebPage::Repository | WebPage::Owner => "",
+            WebPage::Issues => "issues",
+            WebPage::PullRequests => "pulls",
+        }
+    }
+}
+
+/// Page of a repository in the web interface of its provider
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WebPage {
+    Repository,
+    /// The user or organization the repository belongs to
+    Owner,
+    Issues,
+    PullRequests,
+}
+
+struct Github;
+
+impl GitProvider for Github {
+    fn host(&self) -> &str {
+        "github.com"
+    }
+
+    fn token_user(&self) -> &str {
+        "x-access-token"
+    }
+}
+
+struct Gitlab;
+
+impl GitProvider for Gitlab {
+    fn host(&self) -> &str {
+        "gitlab.com"
+    }
+
+    fn page(&self, page: WebPage) -> &str {
+        match page {
+            WebPage::Issues => "-/issues",
+            WebPage::PullRequests => "-/merge_requests",
+            _ => "",
+        }
+    }
+}
+
+struct Bitbucket;
+
+impl GitProvider for Bitbucket {
+    fn host(&self) -> &str {
+        "bitbucket.org"
+    }
+
+    fn token_user(&self) -> &str {
+        "x-token-auth"
+    }
+
+    fn page(&self, page: WebPage) -> &str {
+        match page {
+            WebPage::Issues => "issues",
+            WebPage::PullRequests => "pull-requests",
+            _ => "",
+        }
+    }
+}
+
+/// A self-hosted provider, reachable under its host like the public ones
+struct SelfHosted<'a> {
+    host: &'a str,
+}
+
+impl GitProvider for SelfHosted<'_> {
+    fn host(&self) -> &str {
+        self.host
+    }
+}
+
+/// A local directory of repositories, reachable by their `file://` url
+struct LocalDir<'a> {
+    url: &'a str,
+}
+
+impl GitProvider for LocalDir<'_> {
+    fn host(&self) -> &str {
+        "localhost"
+    }
+
+    fn base_url(&self) -> String {
+        self.url.to_string()
+    }
+
+    /// Local repositories can't be cloned via SSH
+    fn ssh_clone_url(&self, path: &Path) -> String {
+        self.clone_url(path)
+    }
+}
+
+/// Known providers are stored by name, e.g. `github`, custom providers by their host
+impl Serialize for Provider {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Provider {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let provider = String::deserialize(deserializer)?;
+
+        Provider::from(&provider)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown provider {:?}", provider)))
+    }
+}
+
+impl std::str::FromStr for Provider {
+    type Err = String;
+
+    fn from_str(provider: &str) -> Result<Self, Self::Err> {
+        Provider::from(provider).ok_or_else(|| format!("Unknown provider {:?}", provider))
+    }
+}
+
+/// Extracts provider and project path of a remote url
+pub(crate) fn parse_remote_url(remote_url: &str) -> Option<(Provider, PathBuf)> {
+    let remote_url = remote_url.trim();
+    // scp-like syntax, e.g. `git@github.com:user/repo.git`
+    let normalized = match remote_url.split_once(':') {
+        Some((host, path)) if is_ssh_url(remote_url) && !remote_url.starts_with("ssh://") => {
+            format!("ssh://{}/{}", host, path)
+        }
+        _ => remote_url.to_string(),
+    };
+    if let Ok(url) = url::Url::parse(&normalized) {
+        if url.scheme() == "file" {
+            // The last two segments are the owner and the repository, like for the other providers
+            let path = normalize_repository_path(url.path());
+            let path = path.to_string_lossy();
+            let mut segments = path.rsplitn(3, '/');
+            if let (Some(repo), Some(owner), Some(dir)) =
+                (segments.next(), segments.next(), segments.next())
+            {
+                let provider = Provider::Local {
+                    url: format!("file:///{}", dir),
+                };
+
+                return Some((provider, Path::new(owner).join(repo)));
+            }
+            log::error!(
+                "Invalid remote-url {:?}. Could not determine owner.",
+                remote_url
+            );
+        } else if let Some(host) = url.host_str() {
+            if let Some(provider) = Provider::from(host) {
+                return Some((provider, normalize_repository_path(url.path())));
+            } else {
+                log::error!("Could not identify provider for {:?}", host);
+            }
+        } else {
+            log::error!(
+                "Invalid remote-url {:?}. Could not determine host.",
+                remote_url
+            );
+        }
+    } else {
+        log::error!("Could not parse url {:?}", remote_url);
+    }
+
+    None
+}
+
+/// Strips the slashes around the path of a repository and the `.git` suffix of bare repositories,
+/// e.g. `/owner/repo.git/` becomes `owner/repo`
+pub(crate) fn normalize_repository_path(path: &str) -> PathBuf {
+    let path = path.trim_matches('/');
+
+    PathBuf::from(path.strip_suffix(".git").unwrap_or(path))
+}
+
+/// Whether the remote url uses SSH, either as `ssh://` url or with the scp-like syntax
+pub(crate) fn is_ssh_url(remote_url: &str) -> bool {
+    remote_url.starts_with("ssh://")
+        || (!remote_url.contains("://") && remote_url.contains('@') && remote_url.contains(':'))
+}
+
+/// A repository of the GitHub API, only with the fields which are used
+#[derive(Deserialize)]
+struct GithubRepository {
+    full_name: String,
+}
+
+/// Names (`owner/repo`) of the repositories of a page of the GitHub API
+fn github_repository_names(json: &str) -> Result<Vec<String>, String> {
+    let repositories: Vec<GithubRepository> = serde_json::from_str(json)
+        .map_err(|e| format!("Unexpected response of the GitHub API: {}", e))?;
+
+    Ok(repositories
+        .into_iter()
+        .map(|repository| repository.full_name)
+        .collect())
+}
+
+/// Names (`owner/repo`) of all repositories of a GitHub organization, queried with curl.
+/// `WORKSPACE_GITHUB_TOKEN` is sent if set, so private repositories are included
+pub(crate) fn github_org_repositories(org: &str) -> Result<Vec<String>, String> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    const PER_PAGE: usize = 100;
+
+    let api = std::env::var("WORKSPACE_GITHUB_API")
+        .unwrap_or_else(|_| String::from("https://api.github.com"));
+    // Passed on stdin instead of the command line, where other users could see the token
+    let mut config = String::from("header = \"Accept: application/vnd.github+json\"\n");
+    if let Some(token) = std::env::var(Provider::Github.token_var())
+        .ok()
+        .filter(|token| !token.is_empty())
+    {
+        config.push_str(&format!("header = \"Authorization: Bearer {}\"\n", token));
+    }
+
+    let mut names = Vec::new();
+    for page in 1.. {
+        let url = format!(
+            "{}/orgs/{}/repos?per_page={}&page={}",
+            api.trim_end_matches('/'),
+            org,
+            PER_PAGE,
+            page
+        );
+        log::debug!("curl {}", url);
+        let mut child = Command::new("curl")
+            .args(["--silent", "--show-error", "--fail", "--location"])
+            .args(["--config", "-"])
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Could not run curl: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(config.as_bytes())
+                .map_err(|e| format!("Could not pass the headers to curl: {}", e))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|e| format!("Could not run curl: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+
+        let page = github_repository_names(&String::from_utf8_lossy(&output.stdout))?;
+        let last = page.len() < PER_PAGE;
+        names.extend(page);
+        if last {
+            break;
+        }
+    }
+
+    Ok(names)
+}
+
+/// Opens the url with `$BROWSER`, otherwise with the default browser of the platform
+pub fn open_in_browser(url: &str) -> std::io::Result<()> {
+    use std::io;
+
+    let mut command = match std::env::var("BROWSER") {
+        Ok(browser) if !browser.is_empty() => Command::new(browser),
+        _ if cfg!(target_os = "macos") => Command::new("open"),
+        _ if cfg!(windows) => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        _ => Command::new("xdg-open"),
+    };
+    let status = command.arg(url).status()?;
+    if !status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("the browser exited with {}", status),
+        ));
+    }
+
+    Ok(())
+}
+
+impl Provider {
+    pub fn from(provider: &str) -> Option<Self> {
+        match provider {
+            "github" => Some(Self::Github),
+            "github.com" => Some(Self::Github),
+            "gitlab" => Some(Self::Gitlab),
+            "gitlab.com" => Some(Self::Gitlab),
+            "bitbucket" => Some(Self::Bitbucket),
+            "bitbucket.org" => Some(Self::Bitbucket),
+            url if url.starts_with("file://") => Some(Self::Local {
+                url: url.trim_end_matches('/').to_string(),
+            }),
+            // Any other host is considered self-hosted
+            host if host.contains('.') => Some(Self::Custom {
+                host: host.to_string(),
+            }),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        match *self {
+            Self::Github => "github",
+            Self::Gitlab => "gitlab",
+            Self::Bitbucket => "bitbucket",
+            Self::Custom { ref host } => host,
+            Self::Local { ref url } => url,
+        }
+    }
+
+    /// The URL logic of the provider
+    fn urls(&self) -> Box<dyn GitProvider + '_> {
+        match *self {
+            Self::Github => Box::new(Github),
+            Self::Gitlab => Box::new(Gitlab),
+            Self::Bitbucket => Box::new(Bitbucket),
+            Self::Custom { ref host } => Box::new(SelfHosted { host }),
+            Self::Local { ref url } => Box::new(LocalDir { url }),
+        }
+    }
+
+    /// Environment variable with the access token for HTTPS, e.g. `WORKSPACE_GITHUB_TOKEN`
+    /// or `WORKSPACE_GIT_MYCORP_INTERNAL_TOKEN` for `git.mycorp.internal`
+    fn token_var(&self) -> String {
+        let name: String = self
+            .name()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+
+        format!("WORKSPACE_{}_TOKEN", name)
+    }
+
+    /// Url of the page of the repository in the web interface
+    pub fn web_url(&self, path: &Path, page: WebPage) -> String {
+        let urls = self.urls();
+        let path = match page {
+            WebPage::Owner => path.parent().unwrap_or(path),
+            _ => path,
+        };
+        let url = format!("{}/{}", urls.base_url(), path.display());
+
+        match urls.page(page) {
+            "" => url,
+            page => format!("{}/{}", url, page),
+        }
+    }
+
+    /// Git config which sends the access token to the provider, if there is one
+    pub(crate) fn auth_config(&self) -> Option<(String, String)> {
+        use base64::engine::general_purpose::STANDARD;
+        use base64::Engine;
+
+        let token = std::env::var(self.token_var())
+            .ok()
+            .filter(|token| !token.is_empty())?;
+        let urls = self.urls();
+        let credentials = format!("{}:{}", urls.token_user(), token);
+
+        Some((
+            format!("http.{}/.extraHeader", urls.base_url()),
+            format!("Authorization: Basic {}", STANDARD.encode(credentials)),
+        ))
+    }
+
+    pub(crate) fn host(&self) -> &str {
+        match *self {
+            Self::Github => Github.host(),
+            Self::Gitlab => Gitlab.host(),
+            Self::Bitbucket => Bitbucket.host(),
+            Self::Custom { ref host } => host,
+            Self::Local { .. } => "localhost",
+        }
+    }
+
+    pub(crate) fn get_clone_url(&self, path: &Path, ssh: bool) -> String {
+        if ssh {
+            self.urls().ssh_clone_url(path)
+        } else {
+            self.urls().clone_url(path)
+        }
+    }
+
+    /// The remote url if it has to be stored, `None` if the provider builds an equivalent one
+    pub(crate) fn custom_url(&self, path: &Path, remote_url: &str) -> Option<String> {
+        let remote_url = remote_url.trim();
+        let normalize = |url: &str| url.trim_end_matches(".git").to_lowercase();
+        let built = [
+            self.get_clone_url(path, false),
+            self.get_clone_url(path, true),
+        ];
+        if built
+            .iter()
+            .any(|url| normalize(url) == normalize(remote_url))
+        {
+            None
+        } else {
+            Some(remote_url.to_string())
+        }
+    }
+
+    pub(crate) fn git_pull<'a>(
+        &self,
+        repo: &Repository<'a>,
+        strategy: SyncStrategy,
+    ) -> Result<(), Error> {
+        log::info!("- Pull {:?} ({:?})...", repo.git_path, strategy);
+        let git = |args: &[&str]| git_unless_dry_run(repo.ctx, args, &repo.local_path);
+        // Without a remote the upstream of the current branch is used
+        let remote = match repo.remote.as_deref() {
+            Some(remote) => {
+                let branch = repo
+                    .current_branch()
+                    .ok_or_else(|| Error::Failed(String::from("HEAD is not on a branch")))?;
+                Some((remote, branch))
+            }
+            None => None,
+        };
+        // Fetch first and only merge if there is anything new, merging is the slow part
+        let pull = |merge: &[&str]| {
+            let upstream = match &remote {
+                Some((remote, branch)) => {
+                    git(&["fetch", remote])?;
+                    format!("{}/{}", remote, branch)
+                }
+                None => {
+                    git(&["fetch"])?;
+                    String::from("@{u}")
+                }
+            };
+            if repo.contains(&upstream) {
+                log::info!("~ {:?} is up to date", repo.git_path);
+                return Ok(());
+            }
+            git(&[merge, &[upstream.as_str()]].concat())
+        };
+        match strategy {
+            SyncStrategy::Pull => pull(&["merge"]),
+            SyncStrategy::Rebase => pull(&["rebase"]).map_err(|e| {
+                let git_dir = repo.local_path.join(".git");
+                if !git_dir.join("rebase-merge").exists() && !git_dir.join("rebase-apply").exists()
+                {
+                    return e;
+                }
+                // The last line is what ends up in the summary
+                Error::Failed(format!(
+                    "{}\nRebase stopped due to conflicts, resolve them and run \
+                     `git rebase --continue` or `git rebase --abort` in {:?}",
+                    e, repo.local_path
+                ))
+            }),
+            SyncStrategy::FfOnly => pull(&["merge", "--ff-only"]),
+            SyncStrategy::Reset => match &remote {
+                Some((remote, branch)) => {
+                    git(&["fetch", remote])?;
+                    git(&["reset", "--hard", &format!("{}/{}", remote, branch)])
+                }
+                None => {
+                    git(&["fetch"])?;
+                    git(&["reset", "--hard", "@{u}"])
+                }
+            },
+        }
+    }
+
+    pub(crate) fn git_clone<'a>(
+        &self,
+        repo: &Repository<'a>,
+        url: &str,
+        branch: Option<&str>,
+    ) -> Result<(), Error> {
+        log::info!("- Clone {}...", &url);
+        let local_path = repo.local_path.to_string_lossy();
+        let mut args = vec!["clone", url, &local_path];
+        if repo.mirror {
+            // A mirror has all branches and no working tree for submodules
+            args.push("--mirror");
+        } else {
+            if let Some(branch) = branch {
+                args.extend(&["--branch", branch]);
+            }
+            if repo.submodules {
+                args.push("--recurse-submodules");
+            }
+        }
+        execute_unless_dry_run(&mut git_command(repo.ctx, &args, None), repo.ctx.dry_run)?;
+        if let Some(branch) = repo.current_branch().filter(|_| !repo.ctx.dry_run) {
+            log::info!("- Cloned {:?} on branch {}", repo.git_path, branch);
+        }
+
+        Ok(())
+    }
+
+    /// Updates all refs of a mirror, removing the ones deleted on the remote
+    pub(crate) fn git_update_mirror<'a>(&self, repo: &Repository<'a>) -> Result<(), Error> {
+        log::info!("- Update mirror {:?}...", repo.git_path);
+        git_unless_dry_run(repo.ctx, &["remote", "update", "--prune"], &repo.local_path)
+    }
+
+    pub(crate) fn git_checkout<'a>(
+        &self,
+        repo: &Repository<'a>,
+        branch: &str,
+    ) -> Result<(), Error> {
+        log::info!("- Checkout {} in {:?}...", branch, repo.git_path);
+        git_unless_dry_run(repo.ctx, &["checkout", branch], &repo.local_path)
+    }
+
+    pub(crate) fn git_update_submodules<'a>(&self, repo: &Repository<'a>) -> Result<(), Error> {
+        log::info!("- Update submodules of {:?}...", repo.git_path);
+        git_unless_dry_run(
+            repo.ctx,
+            &["submodule", "update", "--init", "--recursive"],
+            &repo.local_path,
+        )
+    }
+
+    pub(crate) fn git_fetch<'a>(&self, repo: &Repository<'a>, flags: &[&str]) -> Result<(), Error> {
+        log::info!("- Fetch {:?}...", repo.git_path);
+        let mut args = vec!["fetch"];
+        args.extend(flags);
+        args.extend(repo.remote.as_deref());
+        git_unless_dry_run(repo.ctx, &args, &repo.local_path)
+    }
+
+    pub(crate) fn git_push<'a>(&self, repo: &Repository<'a>, args: &[&str]) -> Result<(), Error> {
+        log::info!("- Push {:?}...", repo.git_path);
+        let output = git_checked(repo.ctx, &[&["push"], args].concat(), &repo.local_path)?;
+        // git reports the pushed refs on stderr
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        stderr
+            .lines()
+            .filter(|line| line.starts_with(' '))
+            .for_each(|line| log::info!("  {}: {}", repo.git_path.display(), line.trim()));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Project;
+
+    fn project() -> Project {
+        Project::new(Provider::Github, PathBuf::from("owner/repo"))
+    }
+
+    #[test]
+    fn gitlab_round_trip() {
+        let provider = Provider::from("gitlab.com").unwrap();
+        let toml = toml::to_string(&Project {
+            provider,
+            ..project()
+        })
+        .unwrap();
+
+        assert!(toml.contains("provider = \"gitlab\""));
+        assert_eq!(Provider::from("gitlab"), Some(Provider::Gitlab));
+    }
+
+    #[test]
+    fn bitbucket_round_trip() {
+        let provider = Provider::from("bitbucket.org").unwrap();
+        assert_eq!(provider.urls().base_url(), "https://bitbucket.org");
+
+        let toml = toml::to_string(&Project {
+            provider,
+            ..project()
+        })
+        .unwrap();
+
+        assert!(toml.contains("provider = \"bitbucket\""));
+        assert_eq!("bitbucket".parse(), Ok(Provider::Bitbucket));
+        assert_eq!(
+            parse_remote_url("git@bitbucket.org:owner/repo.git"),
+            Some((Provider::Bitbucket, PathBuf::from("owner/repo")))
+        );
+    }
+
+    #[test]
+    fn custom_provider_round_trip() {
+        let provider = Provider::from("git.mycorp.internal").unwrap();
+        assert_eq!(provider.urls().base_url(), "https://git.mycorp.internal");
+
+        let toml = toml::to_string(&Project {
+            provider,
+            ..project()
+        })
+        .unwrap();
+        let project: Project = toml::from_str(&toml).unwrap();
+
+        assert_eq!(
+            project.provider,
+            Provider::Custom {
+                host: String::from("git.mycorp.internal")
+            }
+        );
+    }
+
+    #[test]
+    fn ssh_remote_url() {
+        let remote_url = "git@gitlab.com:owner/repo.git";
+
+        assert!(is_ssh_url(remote_url));
+        assert_eq!(
+            parse_remote_url(remote_url),
+            Some((Provider::Gitlab, PathBuf::from("owner/repo")))
+        );
+        assert_eq!(
+            Provider::Gitlab.get_clone_url(Path::new("owner/repo"), true),
+            remote_url
+        );
+    }
+
+    #[test]
+    fn https_remote_url() {
+        let remote_url = "https://github.com/owner/repo";
+
+        assert!(!is_ssh_url(remote_url));
+        assert_eq!(
+            parse_remote_url(remote_url),
+            Some((Provider::Github, PathBuf::from("owner/repo")))
+        );
+        assert_eq!(
+            Provider::Github.get_clone_url(Path::new("owner/repo"), false),
+            remote_url
+        );
+    }
+
+    #[test]
+    fn github_repository_names_of_page() {
+        let json = r#"[{"full_name": "org/a", "owner": {"login": "org"}},
+            {"name": "b", "full_name": "org/b\u002dc",
+             "template_repository": {"full_name": "other/template"}}]"#;
+
+        assert_eq!(github_repository_names(json).unwrap(), ["org/a", "org/b-c"]);
+        assert!(github_repository_names("[]").unwrap().is_empty());
+        assert!(github_repository_names(r#"{"message": "Not Found"}"#).is_err());
+    }
+
+    #[test]
+    fn access_tokens() {
+        let host = Provider::from("git.my-corp.internal").unwrap();
+        assert_eq!(host.token_var(), "WORKSPACE_GIT_MY_CORP_INTERNAL_TOKEN");
+        assert_eq!(Provider::Github.token_var(), "WORKSPACE_GITHUB_TOKEN");
+
+        let host = Provider::from("tokens.test.internal").unwrap();
+        std::env::set_var(host.token_var(), "abc");
+        assert_eq!(
+            host.auth_config(),
+            Some((
+                String::from("http.https://tokens.test.internal/.extraHeader"),
+                String::from("Authorization: Basic b2F1dGgyOmFiYw==")
+            ))
+        );
+    }
+
+    #[test]
+    fn git_suffix_is_stripped() {
+        for url in &[
+            "https://github.com/owner/repo.git",
+            "https://github.com/owner/repo.git/",
+            "git@github.com:owner/repo.git",
+            "ssh://git@github.com/owner/repo/",
+        ] {
+            let (_, path) = parse_remote_url(url).unwrap();
+            assert_eq!(path, Path::new("owner/repo"), "{}", url);
+        }
+    }
+
+    #[test]
+    fn custom_urls_are_kept() {
+        let custom_url = |url: &str| {
+            let (provider, path) = parse_remote_url(url).unwrap();
+            provider.custom_url(&path, url)
+        };
+
+        assert_eq!(custom_url("https://github.com/owner/repo.git\n"), None);
+        assert_eq!(custom_url("git@github.com:Owner/Repo.git"), None);
+        assert_eq!(
+            custom_url("https://git.mycorp.internal:8443/owner/repo"),
+            Some(String::from("https://git.mycorp.internal:8443/owner/repo"))
+        );
+    }
+}
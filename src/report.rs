@@ -0,0 +1,377 @@
+//! The results of an operation on all projects and how they are logged and summarized.
+
+use crate::git::Error;
+use crate::options::Format;
+use crate::Context;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+thread_local! {
+    /// Path of the project the current thread works on, prefixed to its log lines
+    static LOG_PROJECT: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Prefixes the log lines of the current thread with the path of the project until dropped,
+/// so the lines of projects processed in parallel can be told apart
+pub struct LogProject;
+
+impl LogProject {
+    /// Path of the project the current thread works on, if any
+    pub fn current() -> Option<String> {
+        LOG_PROJECT.with(|project| project.borrow().clone())
+    }
+
+    pub(crate) fn enter(path: &Path) -> Self {
+        LOG_PROJECT.with(|project| *project.borrow_mut() = Some(path.display().to_string()));
+
+        Self
+    }
+}
+
+impl Drop for LogProject {
+    fn drop(&mut self) {
+        LOG_PROJECT.with(|project| *project.borrow_mut() = None);
+    }
+}
+
+/// Quotes and escapes the text as JSON string
+pub(crate) fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+
+    json
+}
+
+/// What a successful operation did to a single project
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Cloned,
+    Pulled,
+    Pushed,
+    Fetched,
+    UpToDate,
+    /// The project is not cloned or pinned
+    Skipped,
+    /// Any other operation which succeeded
+    Done,
+}
+
+impl Outcome {
+    /// All outcomes in the order they are summarized
+    const ALL: [Outcome; 7] = [
+        Self::Cloned,
+        Self::Pulled,
+        Self::Pushed,
+        Self::Fetched,
+        Self::UpToDate,
+        Self::Skipped,
+        Self::Done,
+    ];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Cloned => "cloned",
+            Self::Pulled => "pulled",
+            Self::Pushed => "pushed",
+            Self::Fetched => "fetched",
+            Self::UpToDate => "up-to-date",
+            Self::Skipped => "skipped",
+            Self::Done => "done",
+        }
+    }
+}
+
+impl From<()> for Outcome {
+    fn from(_: ()) -> Self {
+        Self::Done
+    }
+}
+
+/// Result of an operation on a single project
+pub struct ProjectResult {
+    pub path: PathBuf,
+    /// Name of the provider of the project, `None` for hooks
+    pub provider: Option<String>,
+    pub result: Result<Outcome, Error>,
+    pub duration: Duration,
+}
+
+/// Results of an operation on all projects of the workspace
+pub struct Report {
+    pub operation: &'static str,
+    pub results: Vec<ProjectResult>,
+}
+
+impl Report {
+    pub fn failures(&self) -> impl Iterator<Item = (&PathBuf, &Error)> {
+        self.results
+            .iter()
+            .filter_map(|r| r.result.as_ref().err().map(|e| (&r.path, e)))
+    }
+
+    /// The number of projects per outcome, e.g. `3 cloned, 1 up-to-date, 1 failed`.
+    /// Outcomes which did not occur are left out
+    fn counts(&self) -> String {
+        Self::count(&self.results.iter().collect::<Vec<_>>())
+    }
+
+    /// The counts of every provider in the order they were processed, e.g. `github: 3 cloned`
+    fn subtotals(&self) -> Vec<String> {
+        let mut providers: Vec<&str> = Vec::new();
+        for provider in self.results.iter().filter_map(|r| r.provider.as_deref()) {
+            if !providers.contains(&provider) {
+                providers.push(provider);
+            }
+        }
+
+        providers
+            .into_iter()
+            .map(|provider| {
+                let results: Vec<&ProjectResult> = self
+                    .results
+                    .iter()
+                    .filter(|r| r.provider.as_deref() == Some(provider))
+                    .collect();
+                format!("{}: {}", provider, Self::count(&results))
+            })
+            .collect()
+    }
+
+    fn count(results: &[&ProjectResult]) -> String {
+        let failed = results.iter().filter(|r| r.result.is_err()).count();
+        let mut counts: Vec<String> = Outcome::ALL
+            .iter()
+            .map(|&outcome| {
+                let count = results
+                    .iter()
+                    .filter(|r| r.result.as_ref().ok() == Some(&outcome))
+                    .count();
+                (count, outcome.label())
+            })
+            .chain(std::iter::once((failed, "failed")))
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, label)| format!("{} {}", count, label))
+            .collect();
+        if counts.is_empty() {
+            counts.push(String::from("no projects"));
+        }
+
+        counts.join(", ")
+    }
+
+    fn total_duration(&self) -> Duration {
+        self.results.iter().map(|r| r.duration).sum()
+    }
+
+    /// Logs whether the operation passed or failed for every project
+    pub(crate) fn print_summary(&self) {
+        log::info!("Summary of {}:", self.operation);
+        for r in &self.results {
+            let status = if r.result.is_ok() { "passed" } else { "FAILED" };
+            log::info!(" - {:<6} {}", status, r.path.display());
+        }
+    }
+
+    /// Logs the duration of the `limit` slowest projects, slowest first, and the total duration
+    fn print_timings(&self, format: Format, limit: usize) {
+        let mut results: Vec<&ProjectResult> = self.results.iter().collect();
+        results.sort_by_key(|r| std::cmp::Reverse(r.duration));
+        results.truncate(limit);
+
+        match format {
+            Format::Text => {
+                log::info!("Timings of {}:", self.operation);
+                for r in &results {
+                    log::info!(" {:>8.2}s {}", r.duration.as_secs_f64(), r.path.display());
+                }
+                log::info!(" {:>8.2}s total", self.total_duration().as_secs_f64());
+            }
+            Format::Json => {
+                let projects: Vec<String> = results
+                    .iter()
+                    .map(|r| {
+                        format!(
+                            "{{\"path\":{},\"seconds\":{:.3}}}",
+                            json_string(&r.path.display().to_string()),
+                            r.duration.as_secs_f64()
+                        )
+                    })
+                    .collect();
+                println!(
+                    "{{\"operation\":{},\"seconds\":{:.3},\"projects\":[{}]}}",
+                    json_string(self.operation),
+                    self.total_duration().as_secs_f64(),
+                    projects.join(",")
+                );
+            }
+        }
+    }
+
+    /// Writes the outcome, error and duration of every project as JSON
+    fn write_json(&self, file: &Path) -> std::io::Result<()> {
+        use std::fs;
+
+        let projects: Vec<String> = self
+            .results
+            .iter()
+            .map(|r| {
+                let (status, error) = match &r.result {
+                    Ok(outcome) => (outcome.label(), String::from("null")),
+                    Err(e) => ("failed", json_string(&e.to_string())),
+                };
+                format!(
+                    "{{\"path\":{},\"status\":{},\"error\":{},\"seconds\":{:.3}}}",
+                    json_string(&r.path.display().to_string()),
+                    json_string(status),
+                    error,
+                    r.duration.as_secs_f64()
+                )
+            })
+            .collect();
+        let json = format!(
+            "{{\"operation\":{},\"total\":{},\"failed\":{},\"seconds\":{:.3},\"results\":[{}]}}\n",
+            json_string(self.operation),
+            self.results.len(),
+            self.failures().count(),
+            self.total_duration().as_secs_f64(),
+            projects.join(",")
+        );
+
+        fs::write(file, json)
+    }
+
+    /// Writes the results as JUnit XML, each project is a testcase
+    fn write_junit(&self, file: &Path) -> std::io::Result<()> {
+        use std::fs;
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuite name=\"workspace {}\" tests=\"{}\" failures=\"{}\">\n",
+            self.operation,
+            self.results.len(),
+            self.failures().count()
+        ));
+        for r in &self.results {
+            let name = escape_xml(&r.path.display().to_string());
+            let time = r.duration.as_secs_f64();
+            match &r.result {
+                Ok(_) => xml.push_str(&format!(
+                    "  <testcase classname=\"workspace.{}\" name=\"{}\" time=\"{:.3}\"/>\n",
+                    self.operation, name, time
+                )),
+                Err(e) => {
+                    let message = escape_xml(&e.to_string());
+                    xml.push_str(&format!(
+                        "  <testcase classname=\"workspace.{}\" name=\"{}\" time=\"{:.3}\">\n",
+                        self.operation, name, time
+                    ));
+                    xml.push_str(&format!(
+                        "    <failure message=\"{}\">{}</failure>\n",
+                        message, message
+                    ));
+                    xml.push_str("  </testcase>\n");
+                }
+            }
+        }
+        xml.push_str("</testsuite>\n");
+
+        fs::write(file, xml)
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Writes the requested reports and summarizes failures. Returns whether all projects succeeded
+pub fn summarize(report: &Report, ctx: &Context) -> bool {
+    if let Some(limit) = ctx.timings {
+        report.print_timings(ctx.format, limit);
+    }
+    if let Some(file) = &ctx.report_junit {
+        if let Err(e) = report.write_junit(file) {
+            log::error!("Could not write JUnit report {:?}: {}", file, e);
+        }
+    }
+    if let Some(file) = &ctx.summary_json {
+        if let Err(e) = report.write_json(file) {
+            log::error!("Could not write JSON summary {:?}: {}", file, e);
+        }
+    }
+
+    // The bottom line goes to stderr, apart from the log of the single projects
+    eprintln!("Summary of {}: {}", report.operation, report.counts());
+    if ctx.by_provider {
+        report
+            .subtotals()
+            .iter()
+            .for_each(|subtotal| eprintln!(" - {}", subtotal));
+    }
+    let failures: Vec<_> = report.failures().collect();
+    if !failures.is_empty() {
+        eprintln!(
+            "{} of {} projects failed to {}:",
+            failures.len(),
+            report.results.len(),
+            report.operation
+        );
+        for (path, e) in failures {
+            // The full error was already logged, the last line is usually the relevant one
+            let message = e.to_string();
+            let message = message.lines().last().unwrap_or_default();
+            eprintln!(" - {}: {}", path.display(), message);
+        }
+        return false;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_counts() {
+        let result = |path: &str, provider: &str, result| ProjectResult {
+            path: PathBuf::from(path),
+            provider: Some(provider.to_string()),
+            result,
+            duration: Duration::default(),
+        };
+        let report = Report {
+            operation: "sync",
+            results: vec![
+                result("a", "github", Ok(Outcome::Cloned)),
+                result("b", "gitlab", Ok(Outcome::UpToDate)),
+                result("c", "github", Err(Error::Failed(String::from("fatal")))),
+                result("d", "github", Ok(Outcome::UpToDate)),
+            ],
+        };
+
+        assert_eq!(report.counts(), "1 cloned, 2 up-to-date, 1 failed");
+        assert_eq!(
+            report.subtotals(),
+            [
+                "github: 1 cloned, 1 up-to-date, 1 failed",
+                "gitlab: 1 up-to-date"
+            ]
+        );
+    }
+}
@@ -0,0 +1,2199 @@
+//! The workspace file with all projects and the operations on all of them.
+
+use crate::git::{
+    execute, execute_unless_dry_run, git, git_stdout, git_unless_dry_run, Error, Status,
+};
+use crate::options::{
+    glob_match, parse_cmd, AddOptions, Filter, Format, GraphFormat, Layout, ScanPatterns,
+};
+use crate::project::{detect_build_cmd, resolve_program, Git, Health, Project};
+use crate::provider::{
+    github_org_repositories, is_ssh_url, normalize_repository_path, parse_remote_url, Provider,
+};
+use crate::report::{json_string, summarize, LogProject, Outcome, ProjectResult, Report};
+use crate::{expand_env, format_size, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Modification time of the file in seconds since the unix epoch, or 0 if unknown
+fn modified_secs(path: &Path) -> u64 {
+    use std::fs;
+    use std::time::UNIX_EPOCH;
+
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Looks up a workspace file given by name only, e.g. the default `workspace.toml`,
+/// in the directory and its parents, like git looks for `.git`. Any other path is taken as is.
+/// If there is none, it is in the directory, so it is created there
+pub(crate) fn find_workspace_file(dir: &Path, file: &Path) -> PathBuf {
+    if file.components().count() == 1 {
+        if let Some(found) = dir
+            .ancestors()
+            .map(|dir| dir.join(file))
+            .find(|path| path.is_file())
+        {
+            return found;
+        }
+    }
+
+    dir.join(file)
+}
+
+/// Collects the git repositories up to `depth` levels below the directory.
+/// Repositories are not descended into, so nested repositories are not found.
+/// Neither are directories for which `skip` is true.
+fn find_repositories(
+    dir: &Path,
+    depth: usize,
+    skip: &dyn Fn(&Path) -> bool,
+) -> std::io::Result<Vec<PathBuf>> {
+    use std::fs;
+
+    let mut repositories = Vec::new();
+    if depth == 0 {
+        return Ok(repositories);
+    }
+
+    let mut entries = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for path in entries
+        .into_iter()
+        .filter(|path| path.is_dir() && !skip(path))
+    {
+        if path.join(".git").exists() {
+            repositories.push(path);
+        } else if depth > 1 {
+            match find_repositories(&path, depth - 1, skip) {
+                Ok(found) => repositories.extend(found),
+                Err(e) => log::warn!("Could not scan {:?}: {}", path, e),
+            }
+        }
+    }
+
+    Ok(repositories)
+}
+
+/// Writes into a temporary file first and renames it afterwards,
+/// so an interrupted write never leaves a truncated file behind
+fn write_atomic(path: &Path, content: &str) -> std::io::Result<()> {
+    use std::fs;
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, content)?;
+    fs::rename(&tmp, path)
+}
+
+/// Whether the workspace file is written in YAML, by its extension `.yaml` or `.yml`.
+/// All other files are TOML
+fn is_yaml(file: &Path) -> bool {
+    matches!(
+        file.extension().and_then(|extension| extension.to_str()),
+        Some("yaml") | Some("yml")
+    )
+}
+
+/// The comment lines at the beginning of a toml or yaml file, including blank lines between them
+fn leading_comments(toml: &str) -> &str {
+    let mut end = 0;
+    for line in toml.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            break;
+        }
+        end += line.len();
+    }
+
+    &toml[..end]
+}
+
+/// Asks the user a yes/no question on stdin, default is no
+pub(crate) fn confirm(question: &str) -> bool {
+    use std::io::{self, Write};
+
+    print!("{} [y/N] ", question);
+    io::stdout().flush().ok();
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Set by Ctrl-C, so `watch` can finish the current cycle and stop
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Catches Ctrl-C instead of being killed by it. Running git commands still receive it
+fn catch_interrupt() {
+    if let Err(e) = ctrlc::set_handler(|| INTERRUPTED.store(true, Ordering::SeqCst)) {
+        log::warn!("Could not catch Ctrl-C: {}", e);
+    }
+}
+
+/// Checks git, the workspace file and the clones and prints a checklist.
+/// Returns whether no check failed, missing or unknown clones are only warnings
+pub fn doctor(ctx: &mut Context) -> bool {
+    use std::collections::BTreeSet;
+    use std::fs;
+
+    let mut passed = true;
+    let mut check = |ok: bool, message: String| {
+        println!("[{}] {}", if ok { "ok" } else { "FAIL" }, message);
+        passed &= ok;
+    };
+    let warn = |message: String| println!("[warn] {}", message);
+
+    match git(ctx, &["--version"], None) {
+        Ok(output) if output.status.success() => check(
+            true,
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        ),
+        Ok(output) => check(
+            false,
+            format!("git --version exited with {}", output.status),
+        ),
+        Err(e) => check(false, format!("git could not be run: {}", e)),
+    }
+
+    let content = match fs::read_to_string(&ctx.file) {
+        Ok(content) => {
+            check(true, format!("{:?} exists", ctx.file));
+            content
+        }
+        Err(e) => {
+            check(false, format!("{:?} could not be read: {}", ctx.file, e));
+            return passed;
+        }
+    };
+    let value = if is_yaml(&ctx.file) {
+        serde_yaml::from_str(&content).map_err(|e| (e.to_string(), "yaml"))
+    } else {
+        toml::from_str(&content).map_err(|e| (e.to_string(), "toml"))
+    };
+    let value: toml::Value = match value {
+        Ok(value) => value,
+        Err((e, format)) => {
+            check(
+                false,
+                format!("{:?} is no valid {}: {}", ctx.file, format, e),
+            );
+            return passed;
+        }
+    };
+
+    // Checked on the raw value, so every unknown provider is reported, not only the first one
+    let entries = value
+        .get("workspace")
+        .and_then(toml::Value::as_array)
+        .map_or(&[][..], Vec::as_slice);
+    let unknown: Vec<String> = entries
+        .iter()
+        .filter_map(|entry| {
+            let provider = entry.get("provider").and_then(toml::Value::as_str);
+            if provider.and_then(Provider::from).is_some() {
+                return None;
+            }
+            let path = entry.get("path").and_then(toml::Value::as_str);
+            Some(format!(
+                "{} has the unknown provider {:?}",
+                path.unwrap_or("a project without path"),
+                provider.unwrap_or_default()
+            ))
+        })
+        .collect();
+    if unknown.is_empty() {
+        check(
+            true,
+            format!("All {} projects have a known provider", entries.len()),
+        );
+    } else {
+        check(
+            false,
+            format!("{} projects have an unknown provider:", unknown.len()),
+        );
+    }
+    unknown
+        .into_iter()
+        .for_each(|message| println!("       {}", message));
+
+    let mut workspace: Workspace = match value.try_into() {
+        Ok(workspace) => {
+            check(true, format!("{:?} is a valid workspace", ctx.file));
+            workspace
+        }
+        Err(e) => {
+            check(
+                false,
+                format!("{:?} is no valid workspace: {}", ctx.file, e),
+            );
+            return passed;
+        }
+    };
+    workspace.resolve_collisions();
+    ctx.configure(&workspace);
+
+    let configured: BTreeSet<PathBuf> = workspace
+        .projects
+        .iter()
+        .map(|project| project.get_absolute_path(ctx))
+        .collect();
+    let missing: Vec<&Project> = workspace
+        .projects
+        .iter()
+        .filter(|project| !project.is_cloned(ctx))
+        .collect();
+    if missing.is_empty() {
+        check(true, String::from("All projects are cloned"));
+    }
+    for project in missing {
+        warn(format!("{} is not cloned", project.path.display()));
+    }
+
+    let depth = match ctx.layout {
+        Layout::Flat => 1,
+        Layout::OwnerRepo => 2,
+        Layout::ProviderOwnerRepo => 3,
+    };
+    match find_repositories(&ctx.root, depth, &|_| false) {
+        Ok(found) => {
+            let unknown: Vec<PathBuf> = found
+                .into_iter()
+                .filter(|path| !configured.contains(path))
+                .collect();
+            if unknown.is_empty() {
+                check(
+                    true,
+                    format!("All clones in {:?} are in the workspace", ctx.root),
+                );
+            }
+            for path in unknown {
+                warn(format!("{:?} is not in the workspace", path));
+            }
+        }
+        Err(e) => check(false, format!("{:?} could not be scanned: {}", ctx.root, e)),
+    }
+
+    passed
+}
+
+/// A remote detected for a clone directory, valid as long as its `.git/config` is unchanged
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedRemote {
+    mtime: u64,
+    provider: Provider,
+    path: PathBuf,
+    #[serde(default)]
+    ssh: bool,
+    #[serde(default)]
+    url: Option<String>,
+}
+
+/// The commit of every cloned project, written by `freeze` and checked out by `restore`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lock {
+    #[serde(default)]
+    commits: BTreeMap<String, String>,
+}
+
+impl Lock {
+    /// The lock file belongs to the workspace file, e.g. `workspace.lock` to `workspace.toml`
+    fn file(ctx: &Context) -> PathBuf {
+        ctx.file.with_extension("lock")
+    }
+
+    pub fn load(ctx: &Context) -> Result<Self, String> {
+        use std::fs;
+
+        let file = Self::file(ctx);
+        let content =
+            fs::read_to_string(&file).map_err(|e| format!("Could not read {:?}: {}", file, e))?;
+        toml::from_str(&content).map_err(|e| format!("Invalid lock file {:?}: {}", file, e))
+    }
+
+    fn save(&self, ctx: &Context) -> std::io::Result<()> {
+        write_atomic(
+            &Self::file(ctx),
+            &toml::to_string(&self).expect("Failed to serialize lock"),
+        )
+    }
+}
+
+/// Data remembered between runs which is not part of the workspace configuration
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    /// Projects cloned by the last clone run, which did not finish successfully
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clone_progress: Vec<PathBuf>,
+    // Tables have to be serialized last
+    #[serde(default)]
+    remotes: BTreeMap<String, CachedRemote>,
+    /// Where the state was loaded from and is saved to
+    #[serde(skip)]
+    file: PathBuf,
+}
+
+impl State {
+    const FILE: &'static str = ".workspace-state.toml";
+
+    /// Loads the state next to the workspace file,
+    /// a missing or corrupt state file results in an empty state
+    pub fn load(ctx: &Context) -> Self {
+        use std::fs;
+
+        let file = ctx.file.with_file_name(Self::FILE);
+        let state = match fs::read_to_string(&file) {
+            Ok(content) => toml::from_str(&content).unwrap_or_else(|e| {
+                log::warn!("Ignoring corrupt state file {:?}: {}", file, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+
+        Self { file, ..state }
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        write_atomic(
+            &self.file,
+            &toml::to_string(&self).expect("Failed save state"),
+        )
+    }
+
+    fn get_remote(&self, dir: &str, mtime: u64) -> Option<&CachedRemote> {
+        self.remotes.get(dir).filter(|remote| remote.mtime == mtime)
+    }
+
+    fn set_remote(&mut self, dir: String, remote: CachedRemote) {
+        self.remotes.insert(dir, remote);
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Workspace {
+    /// Changing the layout does not move existing clones
+    #[serde(default, skip_serializing_if = "Layout::is_flat")]
+    pub layout: Layout,
+    /// Directory in which the repositories are cloned, relative to the workspace file.
+    /// `--root` and `WORKSPACE_ROOT` take precedence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
+    /// Whether fetch always prunes remote-tracking branches deleted on the remote
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prune: bool,
+    /// Whether the projects are saved sorted by provider and path instead of in the order
+    /// they were added, so concurrent changes of a shared workspace file merge more easily
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub sort: bool,
+    /// Commands run in the clone root before and after pull and sync
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_sync: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_sync: Option<String>,
+    /// Commands run in the clone root before and after build
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_build: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_build: Option<String>,
+    /// Build command of the projects without their own `cmd`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub default_cmd: Vec<String>,
+    #[serde(default, rename = "workspace")]
+    pub projects: Vec<Project>,
+}
+
+impl Workspace {
+    /// Runs the operation on every project with up to `--jobs` threads and collects the results
+    fn run<T, F>(&self, ctx: &Context, operation: &'static str, f: F) -> Report
+    where
+        T: Into<Outcome>,
+        F: Fn(&Project) -> Result<T, Error> + Sync,
+    {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::Mutex;
+        use std::thread;
+
+        let mut order = ctx.order(self.projects.len());
+        if ctx.by_provider {
+            // Stable, so a shuffled order is kept within every provider
+            let name = |index: &usize| self.projects[*index].provider.name();
+            order.sort_by(|a, b| name(a).cmp(name(b)));
+        }
+        let group = Mutex::new(None);
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new(Vec::with_capacity(order.len()));
+        thread::scope(|scope| {
+            for _ in 0..ctx.jobs.clamp(1, order.len().max(1)) {
+                scope.spawn(|| loop {
+                    if ctx.fail_fast && failed.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let position = next.fetch_add(1, Ordering::SeqCst);
+                    let project = match order.get(position) {
+                        Some(&index) => &self.projects[index],
+                        None => break,
+                    };
+                    if ctx.by_provider {
+                        let mut group = group.lock().expect("Poisoned group");
+                        let provider = project.provider.name();
+                        if *group != Some(provider) {
+                            log::info!("{}:", provider);
+                            *group = Some(provider);
+                        }
+                    }
+                    let start = Instant::now();
+                    let log_project = LogProject::enter(&project.path);
+                    let result = if project.enabled {
+                        f(project).map(Into::into)
+                    } else {
+                        log::info!("~ {:?} is disabled", project.path);
+                        Ok(Outcome::Skipped)
+                    };
+                    drop(log_project);
+                    let duration = start.elapsed();
+                    if let Err(e) = &result {
+                        log::error!("{:?} failed: {}", project.path, e);
+                        failed.store(true, Ordering::SeqCst);
+                    }
+
+                    let outcome = result.as_ref().map_or("failed", |outcome| outcome.label());
+                    let result = ProjectResult {
+                        path: project.path.clone(),
+                        provider: Some(project.provider.name().to_string()),
+                        result,
+                        duration,
+                    };
+                    let mut results = results.lock().expect("Poisoned results");
+                    results.push((position, result));
+                    log::info!(
+                        "[{}/{}] {} {}: {}",
+                        results.len(),
+                        order.len(),
+                        operation,
+                        project.path.display(),
+                        outcome
+                    );
+                });
+            }
+        });
+
+        // Report in processing order, regardless of which job finished first
+        let mut results = results.into_inner().expect("Poisoned results");
+        let started = next.load(Ordering::SeqCst).min(order.len());
+        if started < order.len() {
+            log::warn!(
+                "Stopped {} after the first failure, skipped {} repositories",
+                operation,
+                order.len() - started
+            );
+            results.extend((started..order.len()).map(|position| {
+                let project = &self.projects[order[position]];
+                let result = ProjectResult {
+                    path: project.path.clone(),
+                    provider: Some(project.provider.name().to_string()),
+                    result: Ok(Outcome::Skipped),
+                    duration: Duration::default(),
+                };
+                (position, result)
+            }));
+        }
+        results.sort_by_key(|(position, _)| *position);
+
+        Report {
+            operation,
+            results: results.into_iter().map(|(_, result)| result).collect(),
+        }
+    }
+
+    /// Clones like `git_clone`, but remembers every cloned project in the state,
+    /// so an interrupted run can be continued. The progress is cleared once all clones succeeded.
+    pub fn git_clone_tracked(&self, ctx: &Context, state: &mut State) -> Report {
+        use std::sync::Mutex;
+
+        log::info!("Clone...");
+        let tracked = Mutex::new(&mut *state);
+        let report = self.run(ctx, "clone", |project| {
+            let result = ctx.retry(project, "clone", || project.git_clone(ctx));
+            if result.is_ok() && !ctx.dry_run {
+                let mut state = tracked.lock().expect("Poisoned state");
+                state.clone_progress.push(project.path.clone());
+                if let Err(e) = state.save() {
+                    log::warn!("Could not write the state file: {}", e);
+                }
+            }
+
+            result
+        });
+
+        if report.failures().next().is_none() && !ctx.dry_run {
+            state.clone_progress.clear();
+            if let Err(e) = state.save() {
+                log::warn!("Could not write the state file: {}", e);
+            }
+        }
+
+        report
+    }
+
+    pub fn git_push(&self, ctx: &Context, set_upstream: bool) -> Report {
+        log::info!("Push...");
+        self.run(ctx, "push", |project| project.git_push(ctx, set_upstream))
+    }
+
+    pub fn checkout(&self, ctx: &Context, branch: &str) -> Report {
+        log::info!("Checkout {}...", branch);
+        self.run(ctx, "checkout", |project| project.git_checkout(ctx, branch))
+    }
+
+    /// Records the commit of every cloned project in the lock file
+    pub fn freeze(&self, ctx: &Context) {
+        let mut lock = Lock::default();
+        for project in &self.projects {
+            let repo = project.get_repository(ctx);
+            if !repo.exists_local() {
+                log::info!("~ {:?} is not cloned yet", repo.git_path);
+                continue;
+            }
+            match repo.head() {
+                Some(commit) => {
+                    log::info!("{} {}", commit, project.path.display());
+                    lock.commits
+                        .insert(project.path.display().to_string(), commit);
+                }
+                None => log::warn!("~ {:?} has no commit yet", repo.git_path),
+            }
+        }
+
+        let file = Lock::file(ctx);
+        if ctx.dry_run {
+            log::info!("Would write {} commits to {:?}", lock.commits.len(), file);
+        } else if let Err(e) = lock.save(ctx) {
+            log::error!("Could not write {:?}: {}", file, e);
+        } else {
+            log::info!("Wrote {} commits to {:?}", lock.commits.len(), file);
+        }
+    }
+
+    /// Checks out the commits recorded in the lock file
+    pub fn restore(&self, ctx: &Context, lock: &Lock) -> Report {
+        log::info!("Restore...");
+        self.run(ctx, "restore", |project| {
+            let commit = lock.commits.get(&project.path.display().to_string());
+            project.restore(ctx, commit.map(String::as_str))
+        })
+    }
+
+    pub fn build(&self, ctx: &Context) -> Report {
+        log::info!("Build...");
+        let hooks = [
+            ("pre_build", &self.pre_build),
+            ("post_build", &self.post_build),
+        ];
+        let report = self.with_hooks(ctx, "build", hooks, || {
+            self.run(ctx, "build", |project| {
+                project.build(ctx, &self.default_cmd)
+            })
+        });
+        report.print_summary();
+
+        report
+    }
+
+    /// Runs the hook command in the clone root, if it is configured
+    fn hook(ctx: &Context, name: &str, cmd: Option<&String>) -> Result<(), Error> {
+        let cmd = parse_cmd(cmd.map_or("", String::as_str)).map_err(Error::Failed)?;
+        let (program, args) = match cmd.split_first() {
+            Some(cmd) => cmd,
+            None => return Ok(()),
+        };
+        let program = resolve_program(&expand_env(program), &ctx.root, ctx.dry_run)?;
+        let mut command = Command::new(program);
+        command
+            .current_dir(&ctx.root)
+            .args(args.iter().map(|arg| expand_env(arg)));
+        if ctx.dry_run {
+            return execute_unless_dry_run(&mut command, true);
+        }
+
+        log::info!("- Run {} hook...", name);
+        let output = execute(&mut command)?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !stdout.trim().is_empty() {
+            log::info!("Output of {} hook:\n{}", name, stdout.trim_end());
+        }
+
+        Ok(())
+    }
+
+    /// Runs the operation between its pre and post hook. A failing pre hook aborts the operation.
+    /// Failing hooks are reported like failing projects
+    fn with_hooks<F>(
+        &self,
+        ctx: &Context,
+        operation: &'static str,
+        [(pre, pre_cmd), (post, post_cmd)]: [(&str, &Option<String>); 2],
+        f: F,
+    ) -> Report
+    where
+        F: FnOnce() -> Report,
+    {
+        let run_hook = |name: &str, cmd: &Option<String>| {
+            let start = Instant::now();
+            Self::hook(ctx, name, cmd.as_ref()).map_err(|e| {
+                log::error!("The {} hook failed: {}", name, e);
+                ProjectResult {
+                    path: PathBuf::from(format!("{} hook", name)),
+                    provider: None,
+                    result: Err(e),
+                    duration: start.elapsed(),
+                }
+            })
+        };
+
+        if let Err(result) = run_hook(pre, pre_cmd) {
+            return Report {
+                operation,
+                results: vec![result],
+            };
+        }
+        let mut report = f();
+        if let Err(result) = run_hook(post, post_cmd) {
+            report.results.push(result);
+        }
+
+        report
+    }
+
+    /// Keeps only the cloned projects which are on another branch than the recorded one.
+    /// Must not be saved afterwards
+    pub fn retain_off_branch(&mut self, ctx: &Context) {
+        self.projects
+            .retain(|project| project.is_off_branch(project.current_branch(ctx).as_deref()));
+        if self.projects.is_empty() {
+            log::info!("All repositories are on their recorded branch");
+        }
+    }
+
+    /// Keeps only the projects matching the filter. Must not be saved afterwards.
+    pub fn filter(&mut self, filter: &Filter) {
+        let len = self.projects.len();
+        self.projects.retain(|project| filter.matches(project));
+        if self.projects.is_empty() && len > 0 {
+            log::warn!("No repositories match the filter");
+        }
+    }
+
+    /// Keeps only the projects with one of the paths or clone directories. Must not be saved afterwards.
+    /// Returns the first path which matches no project
+    pub fn select<'a>(&mut self, ctx: &Context, paths: &'a [PathBuf]) -> Result<(), &'a Path> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let is_selected = |project: &Project, path: &Path| {
+            project.path == path || project.get_folder(ctx.layout) == path
+        };
+        if let Some(path) = paths
+            .iter()
+            .find(|path| !self.projects.iter().any(|p| is_selected(p, path)))
+        {
+            return Err(path);
+        }
+
+        self.projects
+            .retain(|project| paths.iter().any(|path| is_selected(project, path)));
+
+        Ok(())
+    }
+
+    /// The project with the path or clone directory, otherwise the only one whose path
+    /// contains the query, ignoring case
+    pub fn find(&self, ctx: &Context, query: &str) -> Result<&Project, String> {
+        let path = Path::new(query);
+        if let Some(project) = self
+            .projects
+            .iter()
+            .find(|p| p.path == path || p.get_folder(ctx.layout) == path)
+        {
+            return Ok(project);
+        }
+
+        let lowercase = query.to_lowercase();
+        let matches: Vec<&Project> = self
+            .projects
+            .iter()
+            .filter(|p| p.path.to_string_lossy().to_lowercase().contains(&lowercase))
+            .collect();
+        match matches.as_slice() {
+            [project] => Ok(project),
+            [] => Err(format!("No repository matches {:?}", query)),
+            _ => {
+                let paths: Vec<String> = matches
+                    .iter()
+                    .map(|p| format!(" - {}", p.path.display()))
+                    .collect();
+                Err(format!(
+                    "{} repositories match {:?}:\n{}",
+                    matches.len(),
+                    query,
+                    paths.join("\n")
+                ))
+            }
+        }
+    }
+
+    /// Enables or disables the project with the path or clone directory.
+    /// Returns whether it changed
+    pub fn set_enabled(
+        &mut self,
+        ctx: &Context,
+        path: &Path,
+        enabled: bool,
+    ) -> Result<bool, String> {
+        let project = self
+            .projects
+            .iter_mut()
+            .find(|p| p.path == path || p.get_folder(ctx.layout) == path)
+            .ok_or_else(|| format!("There is no repository {:?} in the workspace", path))?;
+        let state = if enabled { "enabled" } else { "disabled" };
+        if project.enabled == enabled {
+            log::info!("~ {:?} is already {}", project.path, state);
+            return Ok(false);
+        }
+
+        log::info!("- {:?} is {} now", project.path, state);
+        project.enabled = enabled;
+
+        Ok(true)
+    }
+
+    /// Moves the clone of the project with the path or clone directory to `to`,
+    /// relative to the root, and records it as the clone directory of the project
+    pub fn move_clone(&mut self, ctx: &Context, path: &Path, to: &Path) -> Result<(), String> {
+        use std::fs;
+
+        let project = self
+            .projects
+            .iter_mut()
+            .find(|p| p.path == path || p.get_folder(ctx.layout) == path)
+            .ok_or_else(|| format!("There is no repository {:?} in the workspace", path))?;
+        let from = project.get_absolute_path(ctx);
+        if !project.is_cloned(ctx) {
+            return Err(format!("{:?} is not cloned", project.path));
+        }
+        let target = ctx.root.join(to);
+        if target.exists() {
+            return Err(format!("{:?} already exists", target));
+        }
+
+        log::info!("- Move {:?} to {:?}...", from, target);
+        if !ctx.dry_run {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Could not create {:?}: {}", parent, e))?;
+            }
+            fs::rename(&from, &target).map_err(|e| format!("Could not move {:?}: {}", from, e))?;
+        }
+        // The directory of the layout needs no override
+        project.dir = None;
+        if project.get_folder(ctx.layout) != to {
+            project.dir = Some(to.to_path_buf());
+        }
+
+        Ok(())
+    }
+
+    /// Prints everything known about the project with the path or clone directory.
+    /// Returns whether there is such a project
+    pub fn info(&self, ctx: &Context, path: &Path) -> bool {
+        let project = match self
+            .projects
+            .iter()
+            .find(|p| p.path == path || p.get_folder(ctx.layout) == path)
+        {
+            Some(project) => project,
+            None => return false,
+        };
+
+        let repo = project.get_repository(ctx);
+        let or_none = |value: &str| if value.is_empty() { "-" } else { value }.to_string();
+        let mut fields = vec![
+            ("Path", project.path.display().to_string()),
+            ("Provider", project.provider.name().to_string()),
+            ("Clone url", project.clone_url(ctx)),
+            (
+                "Build command",
+                or_none(&project.build_cmd(&self.default_cmd).join(" ")),
+            ),
+            ("Tags", or_none(&project.tags.join(", "))),
+            (
+                "Branch",
+                or_none(project.branch.as_deref().unwrap_or_default()),
+            ),
+            (
+                "Ref",
+                or_none(project.git_ref.as_deref().unwrap_or_default()),
+            ),
+            ("Local path", repo.local_path.display().to_string()),
+            (
+                "Cloned",
+                String::from(if repo.exists_local() { "yes" } else { "no" }),
+            ),
+        ];
+        if repo.exists_local() {
+            fields.push((
+                "Current branch",
+                repo.current_branch()
+                    .filter(|branch| branch != "HEAD")
+                    .unwrap_or_else(|| String::from("(detached)")),
+            ));
+            fields.push(("HEAD", or_none(&repo.head().unwrap_or_default())));
+        }
+
+        let width = fields
+            .iter()
+            .map(|(label, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        for (label, value) in fields {
+            println!(
+                "{:<width$}  {}",
+                format!("{}:", label),
+                value,
+                width = width + 1
+            );
+        }
+
+        true
+    }
+
+    pub fn list(&self, ctx: &Context, only_cloned: bool) {
+        let projects = self
+            .projects
+            .iter()
+            .map(|project| (project, project.is_cloned(ctx)))
+            .filter(|(_, cloned)| *cloned || !only_cloned)
+            .map(|(project, cloned)| {
+                let branch = if cloned {
+                    project.current_branch(ctx)
+                } else {
+                    None
+                };
+                (project, cloned, branch)
+            });
+
+        match ctx.format {
+            Format::Text => projects.for_each(|(project, _, branch)| match branch {
+                Some(branch) if project.is_off_branch(Some(&branch)) => log::info!(
+                    " - {} ({}, expected {})",
+                    project.path.display(),
+                    branch,
+                    project.branch.as_deref().unwrap_or_default()
+                ),
+                Some(branch) => log::info!(" - {} ({})", project.path.display(), branch),
+                None => log::info!(" - {}", project.path.display()),
+            }),
+            Format::Json => {
+                let projects: Vec<String> = projects
+                    .map(|(project, cloned, branch)| {
+                        let cmd: Vec<String> =
+                            project.cmd.iter().map(|arg| json_string(arg)).collect();
+                        format!(
+                            "{{\"path\":{},\"provider\":{},\"cloned\":{},\"branch\":{},\
+                             \"off_branch\":{},\"cmd\":[{}]}}",
+                            json_string(&project.path.display().to_string()),
+                            json_string(project.provider.name()),
+                            cloned,
+                            branch.as_deref().map_or(String::from("null"), json_string),
+                            project.is_off_branch(branch.as_deref()),
+                            cmd.join(",")
+                        )
+                    })
+                    .collect();
+                println!("[{}]", projects.join(","));
+            }
+        }
+    }
+
+    pub fn exec(&self, ctx: &Context, command: &[String]) -> Report {
+        log::info!("Exec {}...", command.join(" "));
+        self.run(ctx, "exec", |project| project.exec(ctx, command))
+    }
+
+    /// Lists the repositories a destructive operation affects and asks whether to proceed.
+    /// `--yes` answers for the user, without a terminal on stdin nobody can, which means no
+    pub fn confirm(
+        &self,
+        ctx: &Context,
+        operation: &str,
+        affects: impl Fn(&Project) -> bool,
+    ) -> bool {
+        use is_terminal::IsTerminal;
+        use std::io;
+
+        if ctx.yes {
+            return true;
+        }
+        if !io::stdin().is_terminal() {
+            return false;
+        }
+        let affected: Vec<&Project> = self.projects.iter().filter(|p| affects(p)).collect();
+        if affected.is_empty() {
+            return true;
+        }
+        println!("{} affects:", operation);
+        affected
+            .iter()
+            .for_each(|project| println!(" - {}", project.path.display()));
+
+        confirm(&format!("{} {} repositories?", operation, affected.len()))
+    }
+
+    pub fn git_clean(&self, ctx: &Context, force: bool, ignored: bool) -> Report {
+        log::info!("Clean...");
+        self.run(ctx, "clean", |project| {
+            project.git_clean(ctx, force, ignored)
+        })
+    }
+
+    /// Syncs, or only fetches, all projects every `interval` until interrupted by Ctrl-C
+    pub fn watch(&self, ctx: &Context, interval: Duration, fetch: bool) {
+        catch_interrupt();
+        let mut cycle = 1;
+        while !INTERRUPTED.load(Ordering::SeqCst) {
+            log::info!("Cycle {}...", cycle);
+            let report = if fetch {
+                self.git_fetch(ctx)
+            } else {
+                self.git_sync(ctx)
+            };
+            summarize(&report, ctx);
+
+            let next = Instant::now() + interval;
+            while Instant::now() < next && !INTERRUPTED.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            cycle += 1;
+        }
+        log::info!("Stopped watching");
+    }
+
+    pub fn git_gc(&self, ctx: &Context, aggressive: bool) -> Report {
+        log::info!("Collect garbage...");
+        self.run(ctx, "gc", |project| project.git_gc(ctx, aggressive))
+    }
+
+    pub fn git_reset(&self, ctx: &Context, clean: bool) -> Report {
+        log::info!("Reset...");
+        self.run(ctx, "reset", |project| {
+            ctx.retry(project, "reset", || project.git_reset(ctx, clean))
+        })
+    }
+
+    pub fn show_builds(&self, format: Format) {
+        match format {
+            Format::Text => {
+                for project in &self.projects {
+                    let cmd = project.build_cmd(&self.default_cmd);
+                    if cmd.is_empty() {
+                        log::info!(" - {}: no build command, skipped", project.path.display());
+                    } else {
+                        log::info!(" - {}: {}", project.path.display(), cmd.join(" "));
+                    }
+                }
+            }
+            Format::Json => {
+                let projects: Vec<String> = self
+                    .projects
+                    .iter()
+                    .map(|project| {
+                        let cmd: Vec<String> = project
+                            .build_cmd(&self.default_cmd)
+                            .iter()
+                            .map(|arg| json_string(arg))
+                            .collect();
+                        format!(
+                            "{{\"path\":{},\"provider\":{},\"cmd\":[{}]}}",
+                            json_string(&project.path.display().to_string()),
+                            json_string(project.provider.name()),
+                            cmd.join(",")
+                        )
+                    })
+                    .collect();
+                println!("[{}]", projects.join(","));
+            }
+        }
+    }
+
+    /// All `depends_on` relations as `(project, dependency)` pairs
+    fn dependencies(&self) -> Vec<(&Path, &Path)> {
+        self.projects
+            .iter()
+            .flat_map(|project| {
+                project
+                    .depends_on
+                    .iter()
+                    .map(move |dependency| (project.get_path(), dependency.as_path()))
+            })
+            .collect()
+    }
+
+    /// Whether the relation `from -> to` is part of a dependency cycle, i.e. `to` reaches `from`
+    fn is_cyclic(edges: &[(&Path, &Path)], from: &Path, to: &Path) -> bool {
+        let mut stack = vec![to];
+        let mut visited = Vec::new();
+        while let Some(node) = stack.pop() {
+            if node == from {
+                return true;
+            }
+            if visited.contains(&node) {
+                continue;
+            }
+            visited.push(node);
+            stack.extend(edges.iter().filter(|(a, _)| *a == node).map(|(_, b)| *b));
+        }
+
+        false
+    }
+
+    pub fn graph(&self, format: GraphFormat) {
+        let edges = self.dependencies();
+        let mut nodes: Vec<&Path> = self.projects.iter().map(Project::get_path).collect();
+        for (_, dependency) in &edges {
+            if !nodes.contains(dependency) {
+                nodes.push(dependency);
+            }
+        }
+
+        let cycles: Vec<bool> = edges
+            .iter()
+            .map(|(from, to)| Self::is_cyclic(&edges, from, to))
+            .collect();
+        if cycles.contains(&true) {
+            log::warn!("The dependency graph contains cycles");
+        }
+
+        match format {
+            GraphFormat::Dot => {
+                println!("digraph workspace {{");
+                for node in &nodes {
+                    println!("    {};", json_string(&node.display().to_string()));
+                }
+                for ((from, to), cyclic) in edges.iter().zip(&cycles) {
+                    println!(
+                        "    {} -> {}{};",
+                        json_string(&from.display().to_string()),
+                        json_string(&to.display().to_string()),
+                        if *cyclic { " [color=red]" } else { "" }
+                    );
+                }
+                println!("}}");
+            }
+            GraphFormat::Mermaid => {
+                let id = |node: &Path| nodes.iter().position(|n| *n == node).unwrap_or_default();
+
+                println!("graph TD");
+                for (i, node) in nodes.iter().enumerate() {
+                    println!("    n{}[\"{}\"]", i, node.display());
+                }
+                for ((from, to), cyclic) in edges.iter().zip(&cycles) {
+                    println!(
+                        "    n{} {} n{}",
+                        id(from),
+                        if *cyclic { "-. cycle .->" } else { "-->" },
+                        id(to)
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn stale_branches(&self, ctx: &Context, days: u64, delete: bool) {
+        log::info!("Stale branches (older than {} days)...", days);
+        for project in &self.projects {
+            let repo = project.get_repository(ctx);
+            if !repo.exists_local() {
+                continue;
+            }
+
+            let branches = repo.stale_branches(days);
+            if branches.is_empty() {
+                continue;
+            }
+
+            log::info!("{}:", repo.git_path.display());
+            branches
+                .iter()
+                .for_each(|branch| log::info!(" - {}", branch));
+
+            if delete
+                && (ctx.yes
+                    || confirm(&format!(
+                        "Delete {} branches of {}?",
+                        branches.len(),
+                        repo.git_path.display()
+                    )))
+            {
+                for branch in &branches {
+                    match git(ctx, &["branch", "-d", branch], Some(&repo.local_path)) {
+                        Ok(ref output) if output.status.success() => {
+                            log::info!("- Deleted {}", branch)
+                        }
+                        _ => log::error!("Could not delete {}", branch),
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn status(&self, ctx: &Context, all_remotes: bool, porcelain: bool) {
+        if porcelain {
+            self.print_porcelain_status(ctx);
+            return;
+        }
+        log::info!("Status...");
+        if !all_remotes {
+            self.print_status(ctx);
+            return;
+        }
+
+        for project in &self.projects {
+            let repo = project.get_repository(ctx);
+            if !repo.exists_local() {
+                log::info!("~ {:?} is not cloned yet", repo.git_path);
+                continue;
+            }
+
+            let branch = repo.current_branch().unwrap_or_default();
+            log::info!("{} ({}):", repo.git_path.display(), branch);
+            for remote in repo.remotes() {
+                let revision = format!("{}/{}", remote, branch);
+                if !repo.has_revision(&revision) {
+                    continue;
+                }
+                if let Some((ahead, behind)) = repo.ahead_behind(&revision) {
+                    log::info!(" - {}: {} ahead, {} behind", revision, ahead, behind);
+                }
+            }
+        }
+    }
+
+    /// Prints branch, working tree state and distance to the upstream as aligned columns
+    fn print_status(&self, ctx: &Context) {
+        let rows: Vec<(String, Option<Status>, String)> = self
+            .projects
+            .iter()
+            .map(|project| {
+                let repo = project.get_repository(ctx);
+                let status = if repo.exists_local() {
+                    repo.status()
+                } else {
+                    None
+                };
+                let current = status
+                    .as_ref()
+                    .map(|status| status.branch.as_deref().unwrap_or("(detached)").to_string());
+                // Clones on another branch than the recorded one stand out
+                let branch = match (&current, &project.branch) {
+                    (Some(current), Some(expected)) if project.is_off_branch(Some(current)) => {
+                        format!("{} (expected {})", current, expected)
+                    }
+                    _ => current.unwrap_or_default(),
+                };
+                (project.path.display().to_string(), status, branch)
+            })
+            .collect();
+
+        let path_width = rows
+            .iter()
+            .map(|(path, _, _)| path.len())
+            .max()
+            .unwrap_or(0)
+            .max("REPOSITORY".len());
+        let branch_width = rows
+            .iter()
+            .map(|(_, _, branch)| branch.len())
+            .max()
+            .unwrap_or(0)
+            .max("(detached)".len());
+        println!(
+            "{:<path_width$}  {:<branch_width$}  {:<5}  {:>5}  {:>6}",
+            "REPOSITORY",
+            "BRANCH",
+            "STATE",
+            "AHEAD",
+            "BEHIND",
+            path_width = path_width,
+            branch_width = branch_width
+        );
+        for (path, status, branch) in &rows {
+            let status = match status {
+                Some(status) => status,
+                None => {
+                    println!("{:<path_width$}  not cloned", path, path_width = path_width);
+                    continue;
+                }
+            };
+
+            let (ahead, behind) = status
+                .ahead_behind
+                .map_or((String::from("-"), String::from("-")), |(a, b)| {
+                    (a.to_string(), b.to_string())
+                });
+            println!(
+                "{:<path_width$}  {:<branch_width$}  {:<5}  {:>5}  {:>6}",
+                path,
+                branch,
+                if status.dirty { "dirty" } else { "clean" },
+                ahead,
+                behind,
+                path_width = path_width,
+                branch_width = branch_width
+            );
+        }
+    }
+
+    /// Prints path, branch, ahead, behind and working tree state of every clone separated by tabs,
+    /// one line per clone. A detached HEAD is printed as `HEAD`, no upstream as `-`
+    fn print_porcelain_status(&self, ctx: &Context) {
+        for project in &self.projects {
+            let repo = project.get_repository(ctx);
+            if !repo.exists_local() {
+                continue;
+            }
+            let status = match repo.status() {
+                Some(status) => status,
+                None => continue,
+            };
+
+            let (ahead, behind) = status
+                .ahead_behind
+                .map_or((String::from("-"), String::from("-")), |(a, b)| {
+                    (a.to_string(), b.to_string())
+                });
+            println!(
+                "{}\t{}\t{}\t{}\t{}",
+                project.path.display(),
+                status.branch.as_deref().unwrap_or("HEAD"),
+                ahead,
+                behind,
+                if status.dirty { "dirty" } else { "clean" }
+            );
+        }
+    }
+
+    /// Sets `origin/<branch>` as upstream of the given or current branch in every cloned repository
+    pub fn set_upstream(&self, ctx: &Context, branch: Option<&str>) {
+        log::info!("Set upstream...");
+        let mut updated = Vec::new();
+        let mut missing = Vec::new();
+        for project in &self.projects {
+            let repo = project.get_repository(ctx);
+            if !repo.exists_local() {
+                log::info!("~ {:?} is not cloned yet", repo.git_path);
+                continue;
+            }
+
+            let branch = match branch.map(str::to_string).or_else(|| repo.current_branch()) {
+                Some(branch) => branch,
+                None => continue,
+            };
+            let upstream = format!("origin/{}", branch);
+            if !repo.has_revision(&format!("refs/heads/{}", branch))
+                || !repo.has_revision(&upstream)
+            {
+                missing.push(repo.git_path);
+                continue;
+            }
+
+            match git_unless_dry_run(
+                ctx,
+                &[
+                    "branch",
+                    &format!("--set-upstream-to={}", upstream),
+                    &branch,
+                ],
+                &repo.local_path,
+            ) {
+                Ok(_) => updated.push(repo.git_path),
+                Err(e) => log::error!("{:?} failed: {}", repo.git_path, e),
+            }
+        }
+
+        log::info!("Updated {} repositories:", updated.len());
+        updated
+            .iter()
+            .for_each(|path| log::info!(" - {}", path.display()));
+        if !missing.is_empty() {
+            log::warn!("{} repositories lack the branch:", missing.len());
+            missing
+                .iter()
+                .for_each(|path| log::warn!(" - {}", path.display()));
+        }
+    }
+
+    /// Checks all projects in parallel and prints a table, or JSON, of their state
+    pub fn health(&self, ctx: &Context) {
+        use std::thread;
+
+        // At most `--jobs` checks run at the same time
+        let mut health: Vec<Health> = Vec::with_capacity(self.projects.len());
+        for chunk in self.projects.chunks(ctx.jobs.max(1)) {
+            thread::scope(|scope| {
+                let handles: Vec<_> = chunk
+                    .iter()
+                    .map(|project| scope.spawn(move || Health::check(project, ctx)))
+                    .collect();
+
+                health.extend(
+                    handles
+                        .into_iter()
+                        .map(|handle| handle.join().expect("Health check panicked")),
+                );
+            });
+        }
+
+        match ctx.format {
+            Format::Text => {
+                let width = health
+                    .iter()
+                    .map(|h| h.path.len())
+                    .max()
+                    .unwrap_or(0)
+                    .max("REPOSITORY".len());
+                println!(
+                    "{:<width$}  {:<6}  {:<5}  {:>5}  {:>6}  {:<11}  {:>10}",
+                    "REPOSITORY",
+                    "CLONED",
+                    "STATE",
+                    "AHEAD",
+                    "BEHIND",
+                    "REMOTE",
+                    "SIZE",
+                    width = width
+                );
+                for h in &health {
+                    if !h.cloned {
+                        println!("{:<width$}  no", h.path, width = width);
+                        continue;
+                    }
+
+                    let (ahead, behind) = h
+                        .ahead_behind
+                        .map_or((String::from("-"), String::from("-")), |(a, b)| {
+                            (a.to_string(), b.to_string())
+                        });
+                    println!(
+                        "{:<width$}  {:<6}  {:<5}  {:>5}  {:>6}  {:<11}  {:>10}",
+                        h.path,
+                        "yes",
+                        if h.dirty { "dirty" } else { "clean" },
+                        ahead,
+                        behind,
+                        if h.reachable {
+                            "reachable"
+                        } else {
+                            "unreachable"
+                        },
+                        format_size(h.size),
+                        width = width
+                    );
+                }
+            }
+            Format::Json => {
+                let health: Vec<String> = health.iter().map(Health::to_json).collect();
+                println!("[{}]", health.join(","));
+            }
+        }
+    }
+
+    /// Prints how many projects there are and how many of them are cloned, in total and per provider
+    pub fn count(&self, ctx: &Context) {
+        let mut providers: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+        for project in &self.projects {
+            let (total, cloned) = providers.entry(project.provider.name()).or_default();
+            *total += 1;
+            if project.get_repository(ctx).exists_local() {
+                *cloned += 1;
+            }
+        }
+        let total = self.projects.len();
+        let cloned: usize = providers.values().map(|(_, cloned)| cloned).sum();
+
+        match ctx.format {
+            Format::Text => {
+                println!("Repositories: {}", total);
+                println!("Cloned:       {}", cloned);
+                println!("Not cloned:   {}", total - cloned);
+                let width = providers.keys().map(|name| name.len()).max().unwrap_or(0);
+                for (name, (total, cloned)) in &providers {
+                    println!(
+                        " - {:<width$}  {} ({} cloned)",
+                        name,
+                        total,
+                        cloned,
+                        width = width
+                    );
+                }
+            }
+            Format::Json => {
+                let providers: Vec<String> = providers
+                    .iter()
+                    .map(|(name, (total, cloned))| {
+                        format!(
+                            "{}:{{\"total\":{},\"cloned\":{}}}",
+                            json_string(name),
+                            total,
+                            cloned
+                        )
+                    })
+                    .collect();
+                println!(
+                    "{{\"total\":{},\"cloned\":{},\"missing\":{},\"providers\":{{{}}}}}",
+                    total,
+                    cloned,
+                    total - cloned,
+                    providers.join(",")
+                );
+            }
+        }
+    }
+
+    pub fn lfs_check(&self, ctx: &Context) {
+        log::info!("Checking for LFS pointer files...");
+        let mut affected = 0;
+        for project in &self.projects {
+            let repo = project.get_repository(ctx);
+            if !repo.exists_local() {
+                continue;
+            }
+
+            let pointers = repo.lfs_pointers();
+            if pointers.is_empty() {
+                continue;
+            }
+
+            affected += 1;
+            log::warn!(
+                "{} contains {} LFS pointer files, run `git lfs pull` in {:?}:",
+                repo.git_path.display(),
+                pointers.len(),
+                repo.local_path
+            );
+            pointers.iter().for_each(|file| log::warn!(" - {}", file));
+        }
+
+        if affected == 0 {
+            log::info!("No LFS pointer files found");
+        }
+    }
+
+    pub fn since_tag(&self, ctx: &Context, pattern: &str) {
+        log::info!("Commits since the latest tag matching {:?}...", pattern);
+        for project in &self.projects {
+            let repo = project.get_repository(ctx);
+            if !repo.exists_local() {
+                log::info!("~ {:?} is not cloned yet", repo.git_path);
+                continue;
+            }
+
+            match repo.commits_since_tag(pattern) {
+                Some((tag, count)) => log::info!(
+                    " - {}: {} commits since {}",
+                    repo.git_path.display(),
+                    count,
+                    tag
+                ),
+                None => log::info!(" - {}: untagged", repo.git_path.display()),
+            }
+        }
+    }
+
+    /// Prints the latest commits of every cloned repository, grouped by repository
+    pub fn log(&self, ctx: &Context, count: usize, since: Option<&str>) {
+        for project in &self.projects {
+            let repo = project.get_repository(ctx);
+            if !repo.exists_local() {
+                log::debug!("~ {:?} is not cloned yet", repo.git_path);
+                continue;
+            }
+
+            let commits = repo.recent_commits(count, since);
+            if commits.is_empty() {
+                continue;
+            }
+            println!("{}:", repo.git_path.display());
+            commits.iter().for_each(|commit| println!("  {}", commit));
+        }
+    }
+
+    /// Prints the uncommitted changes of every cloned repository, clean ones are left out
+    pub fn diff(&self, ctx: &Context, name_only: bool) {
+        for project in self.projects.iter().filter(|project| !project.mirror) {
+            let repo = project.get_repository(ctx);
+            if !repo.exists_local() {
+                log::debug!("~ {:?} is not cloned yet", repo.git_path);
+                continue;
+            }
+
+            let changes = repo.diff(name_only);
+            if changes.is_empty() {
+                continue;
+            }
+            println!("{}:", repo.git_path.display());
+            changes.iter().for_each(|change| println!("  {}", change));
+        }
+    }
+
+    /// Loads the workspace file, YAML if it ends with `.yaml` or `.yml` and TOML otherwise.
+    /// Fails if it is invalid or missing, unless `missing_ok` is set
+    pub fn load(file: &Path, missing_ok: bool) -> Result<Self, String> {
+        use std::fs;
+
+        let mut workspace: Self = match fs::read(file) {
+            Ok(content) => {
+                let content = String::from_utf8_lossy(&content);
+                if is_yaml(file) {
+                    serde_yaml::from_str(&content).map_err(|e| e.to_string())
+                } else {
+                    toml::from_str(&content).map_err(|e| e.to_string())
+                }
+                .map_err(|e| format!("Invalid workspace file {:?}: {}", file, e))?
+            }
+            // The workspace file is created by the first added repository
+            Err(_) if missing_ok => Self::default(),
+            Err(e) => return Err(format!("Could not read workspace file {:?}: {}", file, e)),
+        };
+        workspace.resolve_collisions();
+
+        Ok(workspace)
+    }
+
+    /// Git config with the access tokens of all providers of the workspace
+    pub fn auth_config(&self) -> Vec<(String, String)> {
+        let mut providers: Vec<&Provider> = Vec::new();
+        for project in &self.projects {
+            if !providers.contains(&&project.provider) {
+                providers.push(&project.provider);
+            }
+        }
+
+        providers
+            .into_iter()
+            .filter_map(Provider::auth_config)
+            .collect()
+    }
+
+    /// Stores the branch of the freshly cloned projects which have no branch yet.
+    /// Returns how many branches were stored
+    pub fn save_branches(&mut self, ctx: &Context, cloned: &[&Path]) -> usize {
+        let mut saved = 0;
+        for project in &mut self.projects {
+            if project.branch.is_some() || !cloned.contains(&project.path.as_path()) {
+                continue;
+            }
+            let branch = project
+                .get_repository(ctx)
+                .current_branch()
+                .filter(|branch| branch != "HEAD");
+            if let Some(branch) = branch {
+                log::info!("- Store branch {} of {:?}", branch, project.path);
+                project.branch = Some(branch);
+                saved += 1;
+            }
+        }
+
+        saved
+    }
+
+    /// Saves the workspace file unless `--dry-run` or `--no-save` is given.
+    /// With `--no-save` the repositories it would contain are listed instead
+    pub fn persist(&mut self, ctx: &Context) -> std::io::Result<()> {
+        if ctx.no_save {
+            log::info!("Not saving {:?}, it would contain:", ctx.file);
+            self.list(ctx, false);
+        } else if !ctx.dry_run {
+            self.save(&ctx.file)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the workspace file atomically, so an interrupted save can't corrupt it.
+    /// The file is serialized from scratch in the format of its extension,
+    /// only the comments at its top are kept
+    pub fn save(&mut self, file: &Path) -> std::io::Result<()> {
+        use std::fs;
+
+        if self.sort {
+            // The clone directories of colliding projects were already assigned in their old order
+            self.projects
+                .sort_by(|a, b| (a.provider.name(), &a.path).cmp(&(b.provider.name(), &b.path)));
+        }
+
+        let existing = fs::read_to_string(file).unwrap_or_default();
+        let mut content = leading_comments(&existing).to_string();
+        if is_yaml(file) {
+            content.push_str(&serde_yaml::to_string(&self).expect("Failed save workspace.yaml"));
+        } else {
+            content.push_str(&toml::to_string(&self).expect("Failed save workspace.toml"));
+        }
+        write_atomic(file, &content)
+    }
+
+    /// Adds the repository at the path, cloned via SSH if its remote uses SSH or `ssh` is set.
+    /// If the repository is not where the layout would clone it, its location is recorded as `dir`.
+    /// Returns whether a new project was added.
+    pub fn add(
+        &mut self,
+        ctx: &Context,
+        path: &Path,
+        options: AddOptions,
+        state: &mut State,
+    ) -> std::io::Result<bool> {
+        use std::env;
+        use std::fs;
+
+        let current_dir = env::current_dir()?;
+        let git_path = current_dir.join(path).join(".git");
+        if git_path.exists() {
+            let AddOptions {
+                cmd,
+                infer_cmd,
+                ssh,
+                tags,
+                remote,
+            } = options;
+            let cmd = match cmd {
+                Some(cmd) => parse_cmd(&cmd)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?,
+                None if infer_cmd => detect_build_cmd(&current_dir.join(path)).unwrap_or_default(),
+                None => Vec::new(),
+            };
+            let remote_name = remote.as_deref().unwrap_or("origin").to_string();
+            // Nothing to record if HEAD is detached
+            let branch = git_stdout(ctx, &["symbolic-ref", "--short", "HEAD"], Some(path));
+            // Relative to the root if possible, so the workspace file stays portable
+            let location = fs::canonicalize(current_dir.join(path))?;
+            let root = fs::canonicalize(&ctx.root).unwrap_or_else(|_| ctx.root.clone());
+            let location = location
+                .strip_prefix(&root)
+                .map_or(location.clone(), Path::to_path_buf);
+            let project = |provider, path, ssh, url| {
+                let mut project = Project {
+                    cmd,
+                    url,
+                    ssh,
+                    branch,
+                    tags,
+                    remote: remote.filter(|remote| remote != "origin"),
+                    ..Project::new(provider, path)
+                };
+                if project.get_folder(ctx.layout) != location {
+                    project.dir = Some(location);
+                }
+
+                project
+            };
+
+            // Only the origin remote is cached
+            let key = current_dir.join(path).display().to_string();
+            let mtime = modified_secs(&git_path.join("config"));
+            let cached = state
+                .get_remote(&key, mtime)
+                .filter(|_| remote_name == "origin");
+            if let Some(remote) = cached {
+                log::debug!("Using cached remote of {:?}", path);
+                let (provider, path) = (remote.provider.clone(), remote.path.clone());
+                let project = project(provider, path, ssh || remote.ssh, remote.url.clone());
+                return Ok(self.add_project(project));
+            } else if let Ok(output) = git(
+                ctx,
+                &["config", "--get", &format!("remote.{}.url", remote_name)],
+                Some(path),
+            ) {
+                let remote_url = String::from_utf8_lossy(&output.stdout);
+                if let Some((provider, path)) = parse_remote_url(&remote_url) {
+                    let is_ssh = is_ssh_url(remote_url.trim());
+                    let url = provider.custom_url(&path, &remote_url);
+                    if remote_name == "origin" {
+                        let remote = CachedRemote {
+                            mtime,
+                            provider: provider.clone(),
+                            path: path.clone(),
+                            ssh: is_ssh,
+                            url: url.clone(),
+                        };
+                        state.set_remote(key, remote);
+                    }
+                    return Ok(self.add_project(project(provider, path, ssh || is_ssh, url)));
+                }
+            } else {
+                log::error!("Invalid remote for {:?}", path);
+            }
+        } else {
+            log::warn!("{:?} is not a git repository", path);
+        }
+
+        Ok(false)
+    }
+
+    /// Adds the project behind the given remote url, without requiring a clone.
+    /// Returns whether a new project was added, fails if the url or the command is invalid
+    pub fn add_url(&mut self, remote_url: &str, options: AddOptions) -> Result<bool, String> {
+        let (provider, path) =
+            parse_remote_url(remote_url).ok_or_else(|| String::from("not a valid remote url"))?;
+        let cmd = match options.cmd.as_deref() {
+            Some(cmd) => parse_cmd(cmd)?,
+            None => Vec::new(),
+        };
+
+        Ok(self.add_project(Project {
+            cmd,
+            url: provider.custom_url(&path, remote_url),
+            ssh: options.ssh || is_ssh_url(remote_url),
+            tags: options.tags,
+            ..Project::new(provider, path)
+        }))
+    }
+
+    /// Returns whether the project was added, i.e. it was not already part of the workspace
+    fn add_project(&mut self, mut project: Project) -> bool {
+        // Matches the path of the same repository added by another url
+        project.path = normalize_repository_path(&project.path.to_string_lossy());
+        if self
+            .projects
+            .iter()
+            .any(|p| p.path == project.path && p.provider == project.provider)
+        {
+            return false;
+        }
+
+        self.disambiguate(&mut project);
+        log::info!(
+            "Found path {:?} with provider {:?}",
+            project.path,
+            project.provider
+        );
+        self.projects.push(project);
+
+        true
+    }
+
+    /// Suffixes the clone directory of the project with its owner, e.g. `repo-owner`,
+    /// if another project already uses the same directory
+    fn disambiguate(&self, project: &mut Project) {
+        let is_taken = |folder: &Path| {
+            self.projects
+                .iter()
+                .any(|p| p.get_folder(self.layout) == folder)
+        };
+
+        let folder = project.get_folder(self.layout);
+        if project.dir.is_some() || !is_taken(&folder) {
+            return;
+        }
+
+        let owner = project
+            .get_path()
+            .parent()
+            .and_then(Path::file_name)
+            .map_or(String::from("other"), |owner| {
+                owner.to_string_lossy().into_owned()
+            });
+        let base = format!("{}-{}", folder.display(), owner);
+        let mut candidate = PathBuf::from(&base);
+        let mut counter = 2;
+        while is_taken(&candidate) {
+            candidate = PathBuf::from(format!("{}-{}", base, counter));
+            counter += 1;
+        }
+
+        log::info!(
+            "~ {:?} collides with another project, using {:?}",
+            project.get_path(),
+            candidate
+        );
+        project.dir = Some(candidate);
+    }
+
+    /// Assigns unique clone directories to hand-written projects whose names collide
+    fn resolve_collisions(&mut self) {
+        let projects = std::mem::take(&mut self.projects);
+        for mut project in projects {
+            self.disambiguate(&mut project);
+            self.projects.push(project);
+        }
+    }
+
+    pub fn import_urls(&mut self, file: &Path) -> std::io::Result<()> {
+        use std::fs;
+
+        let content = fs::read_to_string(file)?;
+        let mut added = 0;
+        let mut invalid = Vec::new();
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match self.add_url(line, AddOptions::default()) {
+                Ok(true) => added += 1,
+                Ok(false) => log::info!("~ {} is already part of the workspace", line),
+                Err(_) => invalid.push(line),
+            }
+        }
+
+        log::info!("Imported {} repositories from {:?}", added, file);
+        if !invalid.is_empty() {
+            log::warn!("Could not import {} urls:", invalid.len());
+            invalid.iter().for_each(|url| log::warn!(" - {}", url));
+        }
+
+        Ok(())
+    }
+
+    /// Adds all repositories of the GitHub organization
+    pub fn import_org(&mut self, org: &str) -> Result<(), String> {
+        let names = github_org_repositories(org)?;
+        let mut added = 0;
+        for name in &names {
+            if self.add_project(Project::new(Provider::Github, PathBuf::from(name))) {
+                added += 1;
+            } else {
+                log::info!("~ {} is already part of the workspace", name);
+            }
+        }
+
+        log::info!(
+            "Imported {} of {} repositories of {}",
+            added,
+            names.len(),
+            org
+        );
+
+        Ok(())
+    }
+
+    /// Sets the build command of all projects whose path matches the pattern.
+    /// Returns how many projects changed, fails if the command is invalid
+    pub fn set_cmd(&mut self, pattern: &str, cmd: &str, dry_run: bool) -> Result<usize, String> {
+        let cmd = parse_cmd(cmd)?;
+        let mut changed = 0;
+        for project in &mut self.projects {
+            if !glob_match(pattern, &project.path.to_string_lossy()) || project.cmd == cmd {
+                continue;
+            }
+
+            log::info!(
+                "- {}: {:?} -> {:?}",
+                project.path.display(),
+                project.cmd.join(" "),
+                cmd.join(" ")
+            );
+            if !dry_run {
+                project.cmd = cmd.clone();
+            }
+            changed += 1;
+        }
+
+        Ok(changed)
+    }
+
+    /// Removes all projects which are not cloned, returns how many were removed
+    pub fn prune(&mut self, ctx: &Context) -> usize {
+        let len = self.projects.len();
+        self.projects.retain(|project| {
+            let cloned = project.is_cloned(ctx);
+            if !cloned {
+                let action = if ctx.dry_run {
+                    "Would remove"
+                } else {
+                    "Remove"
+                };
+                log::info!("- {} {}, it is not cloned", action, project.path.display());
+            }
+
+            cloned || ctx.dry_run
+        });
+
+        if ctx.dry_run {
+            self.projects.iter().filter(|p| !p.is_cloned(ctx)).count()
+        } else {
+            len - self.projects.len()
+        }
+    }
+
+    /// Compares the remote of every clone with its project, e.g. to notice a remote switched to
+    /// a fork. With `fix` the projects are changed to match their remote, keeping their clone
+    /// directory. Returns the number of mismatches
+    pub fn verify(&mut self, ctx: &Context, fix: bool) -> usize {
+        let normalize = |path: &Path| path.to_string_lossy().to_lowercase();
+        let mut mismatches = 0;
+        for project in &mut self.projects {
+            let remote = project
+                .remote
+                .clone()
+                .unwrap_or_else(|| String::from("origin"));
+            let local_path = project.get_absolute_path(ctx);
+            if !project.is_cloned(ctx) {
+                continue;
+            }
+            let url = match git_stdout(ctx, &["remote", "get-url", &remote], Some(&local_path)) {
+                Some(url) => url,
+                None => {
+                    log::warn!("~ {:?} has no remote {}", project.path, remote);
+                    continue;
+                }
+            };
+            let (provider, path) = match parse_remote_url(&url) {
+                Some(parsed) => parsed,
+                None => {
+                    log::warn!("~ {:?}: {} is no url of a provider", project.path, url);
+                    continue;
+                }
+            };
+            if provider == project.provider && normalize(&path) == normalize(&project.path) {
+                log::debug!("~ {:?} matches its remote", project.path);
+                continue;
+            }
+
+            mismatches += 1;
+            log::warn!(
+                "- {:?} ({}) is a clone of {:?} ({}) according to {}",
+                project.path,
+                project.provider.name(),
+                path,
+                provider.name(),
+                remote
+            );
+            if fix {
+                let folder = project.get_folder(ctx.layout);
+                project.url = provider.custom_url(&path, &url);
+                project.ssh |= is_ssh_url(&url);
+                project.provider = provider;
+                project.path = path;
+                if project.get_folder(ctx.layout) != folder {
+                    project.dir = Some(folder);
+                }
+            }
+        }
+
+        mismatches
+    }
+
+    pub fn remove(&mut self, path: &Path, provider: Provider) -> Option<Project> {
+        let index = self
+            .projects
+            .iter()
+            .position(|p| p.path == path && p.provider == provider)?;
+        log::info!("Path {:?} with provider {:?} was removed", path, provider);
+
+        Some(self.projects.remove(index))
+    }
+
+    /// Adds all repositories found up to `depth` levels below the path
+    pub fn scan(
+        &mut self,
+        ctx: &Context,
+        path: Option<PathBuf>,
+        depth: usize,
+        mut patterns: ScanPatterns,
+        infer_cmd: bool,
+        state: &mut State,
+    ) -> std::io::Result<()> {
+        use std::env;
+        use std::fs;
+
+        let current_dir = env::current_dir()?;
+        let path = path.map_or(ctx.root.clone(), |path| {
+            current_dir.join(expand_env(&path.to_string_lossy()))
+        });
+        if let Ok(ignore) = fs::read_to_string(path.join(".workspaceignore")) {
+            log::debug!(
+                "Excluding the patterns of {:?}",
+                path.join(".workspaceignore")
+            );
+            patterns.exclude.extend(ScanPatterns::parse_ignore(&ignore));
+        }
+
+        log::info!("Scanning {:?}...", path);
+
+        let relative = |found: &Path| found.strip_prefix(&path).unwrap_or(found).to_path_buf();
+        let excluded = |found: &Path| patterns.excludes(&relative(found));
+        let repositories = find_repositories(&path, depth, &excluded)?;
+        // Clones of known projects are skipped before their remote is read, the expensive part
+        let known: Vec<PathBuf> = self
+            .projects
+            .iter()
+            .filter_map(|project| fs::canonicalize(project.get_absolute_path(ctx)).ok())
+            .collect();
+
+        let mut added = 0;
+        let mut known_clones = 0;
+        let mut skipped = 0;
+        for path in repositories {
+            if !patterns.includes(&relative(&path)) {
+                log::debug!("{:?} is not included", path);
+                continue;
+            }
+            if fs::canonicalize(&path).map_or(false, |path| known.contains(&path)) {
+                log::debug!("{:?} is already known", path);
+                known_clones += 1;
+                continue;
+            }
+            let options = AddOptions {
+                infer_cmd,
+                ..AddOptions::default()
+            };
+            match self.add(ctx, &path, options, state) {
+                Ok(true) => added += 1,
+                _ => skipped += 1,
+            }
+        }
+        log::info!(
+            "Added {} new repositories, skipped {} known clones and {} duplicate or invalid ones",
+            added,
+            known_clones,
+            skipped
+        );
+
+        Ok(())
+    }
+}
+
+impl Git for Workspace {
+    type Output = Report;
+
+    fn git_pull(&self, ctx: &Context) -> Self::Output {
+        log::info!("Pull...");
+        let hooks = [("pre_sync", &self.pre_sync), ("post_sync", &self.post_sync)];
+        self.with_hooks(ctx, "pull", hooks, || {
+            self.run(ctx, "pull", |project| {
+                ctx.retry(project, "pull", || project.git_pull(ctx))
+            })
+        })
+    }
+
+    fn git_clone(&self, ctx: &Context) -> Self::Output {
+        log::info!("Clone...");
+        self.run(ctx, "clone", |project| {
+            ctx.retry(project, "clone", || project.git_clone(ctx))
+        })
+    }
+
+    fn git_fetch(&self, ctx: &Context) -> Self::Output {
+        log::info!("Fetch...");
+        self.run(ctx, "fetch", |project| {
+            ctx.retry(project, "fetch", || project.git_fetch(ctx))
+        })
+    }
+
+    fn git_sync(&self, ctx: &Context) -> Self::Output {
+        log::info!("Synchronize...");
+        let hooks = [("pre_sync", &self.pre_sync), ("post_sync", &self.post_sync)];
+        self.with_hooks(ctx, "sync", hooks, || {
+            self.run(ctx, "sync", |project| project.git_sync(ctx))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(layout: Layout) -> Context {
+        let file = Path::new("workspace.toml");
+        let mut ctx = Context::new(file, Some(Path::new("/workspace"))).unwrap();
+        ctx.layout = layout;
+
+        ctx
+    }
+
+    fn project() -> Project {
+        Project::new(Provider::Github, PathBuf::from("owner/repo"))
+    }
+
+    #[test]
+    fn layout_from_toml() {
+        let workspace: Workspace = toml::from_str("layout = \"owner-repo\"").unwrap();
+
+        assert_eq!(workspace.layout, Layout::OwnerRepo);
+    }
+
+    #[test]
+    fn nested_layout_alias() {
+        let workspace: Workspace = toml::from_str("layout = \"nested\"").unwrap();
+
+        assert_eq!(workspace.layout, Layout::ProviderOwnerRepo);
+    }
+
+    #[test]
+    fn unknown_provider_is_rejected() {
+        let error = toml::from_str::<Workspace>(
+            "[[workspace]]\nprovider = \"githup\"\npath = \"owner/repo\"\ncmd = []",
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(error.contains("unknown provider \"githup\""), "{}", error);
+        assert!(error.contains("workspace.provider"), "{}", error);
+    }
+
+    #[test]
+    fn keeps_leading_comments() {
+        let toml = "# Repositories of the team\n\n# Keep sorted\nlayout = \"flat\"\n# Not kept\n";
+
+        assert_eq!(
+            leading_comments(toml),
+            "# Repositories of the team\n\n# Keep sorted\n"
+        );
+        assert_eq!(leading_comments("[[workspace]]\n"), "");
+    }
+
+    #[test]
+    fn select_by_path_or_folder() {
+        let ctx = context(Layout::Flat);
+        let mut workspace: Workspace = toml::from_str("").unwrap();
+        workspace.projects = vec![
+            project(),
+            Project::new(Provider::Github, PathBuf::from("owner/other")),
+        ];
+
+        let missing = [PathBuf::from("repo"), PathBuf::from("owner/missing")];
+        assert_eq!(
+            workspace.select(&ctx, &missing),
+            Err(Path::new("owner/missing"))
+        );
+        assert_eq!(workspace.projects.len(), 2);
+
+        let folder = [PathBuf::from("repo")];
+        assert_eq!(workspace.select(&ctx, &folder), Ok(()));
+        assert_eq!(workspace.projects.len(), 1);
+        assert_eq!(workspace.projects[0].path, Path::new("owner/repo"));
+    }
+
+    #[test]
+    fn load_missing_workspace_file() {
+        let file = Path::new("/nonexistent/workspace.toml");
+        let error = Workspace::load(file, false).unwrap_err();
+        assert!(
+            error.starts_with("Could not read workspace file"),
+            "{}",
+            error
+        );
+
+        let workspace = Workspace::load(file, true).unwrap();
+        assert!(workspace.projects.is_empty());
+    }
+}
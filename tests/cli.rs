@@ -0,0 +1,1143 @@
+//! End-to-end tests running the binary against bare repositories in a temporary directory,
+//! which are cloned via the `file://` provider
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A temporary directory with an `upstream` directory of bare repositories,
+/// a `workspace.toml` and a `clones` directory as the root of the workspace
+struct Sandbox {
+    dir: PathBuf,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "workspace-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("upstream")).unwrap();
+        fs::create_dir_all(dir.join("clones")).unwrap();
+
+        Self { dir }
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.dir.join(path)
+    }
+
+    fn provider(&self) -> String {
+        format!("file://{}", self.path("upstream").display())
+    }
+
+    /// Runs git isolated from the global and system config of the user
+    fn git(&self, args: &[&str], dir: &Path) -> String {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(dir)
+            .env("HOME", &self.dir)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_AUTHOR_NAME", "Test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "Test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?}: {:?}", args, output);
+
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
+    }
+
+    /// Creates the bare repository `upstream/<path>` with a single commit adding `README`
+    fn upstream(&self, path: &str) {
+        let bare = self.path("upstream").join(path);
+        fs::create_dir_all(&bare).unwrap();
+        self.git(&["init", "--bare", "-q", "-b", "main"], &bare);
+
+        let seed = self.path("seed").join(path);
+        fs::create_dir_all(&seed).unwrap();
+        self.git(&["init", "-q", "-b", "main"], &seed);
+        self.git(&["remote", "add", "origin", bare.to_str().unwrap()], &seed);
+        self.commit(path, "README");
+    }
+
+    /// Commits a new file to the upstream repository
+    fn commit(&self, path: &str, file: &str) {
+        let seed = self.path("seed").join(path);
+        fs::write(seed.join(file), file).unwrap();
+        self.git(&["add", file], &seed);
+        self.git(&["commit", "-q", "-m", file], &seed);
+        self.git(&["push", "-q", "origin", "main"], &seed);
+    }
+
+    fn write_workspace(&self, paths: &[&str]) {
+        let projects: Vec<String> = paths
+            .iter()
+            .map(|path| {
+                format!(
+                    "[[workspace]]\nprovider = \"{}\"\npath = \"{}\"\ncmd = []\n",
+                    self.provider(),
+                    path
+                )
+            })
+            .collect();
+        fs::write(self.path("workspace.toml"), projects.join("\n")).unwrap();
+    }
+
+    fn workspace_file(&self) -> String {
+        fs::read_to_string(self.path("workspace.toml")).unwrap()
+    }
+
+    /// Runs the binary in the sandbox
+    fn run(&self, args: &[&str]) -> Output {
+        // An absolute file is not looked up in the parent directories
+        let file = self.path("workspace.toml");
+        let file = file.to_str().unwrap();
+        self.run_in(
+            &self.dir,
+            &[&["--root", "clones", "--file", file], args].concat(),
+        )
+    }
+
+    /// Runs the binary in the directory, without a default for the workspace file or root
+    fn run_in(&self, dir: &Path, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_workspace"))
+            .args(["--color", "never"])
+            .args(args)
+            .current_dir(dir)
+            .env("HOME", &self.dir)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env_remove("WORKSPACE_FILE")
+            .env_remove("WORKSPACE_ROOT")
+            .output()
+            .unwrap()
+    }
+
+    /// Runs the binary and fails the test if it fails, returns stdout and stderr
+    fn run_ok(&self, args: &[&str]) -> String {
+        let output = self.run(args);
+        let log = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(output.status.success(), "workspace {:?}:\n{}", args, log);
+
+        log
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn clone_fetch_and_pull() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+
+    let log = sandbox.run_ok(&["clone"]);
+    assert!(log.contains("Summary of clone: 1 cloned"), "{}", log);
+    // The log lines of an operation are prefixed with the repository
+    assert!(log.contains("[workspace] [owner/repo] - Clone"), "{}", log);
+    let clone = sandbox.path("clones/repo");
+    assert!(clone.join("README").exists());
+
+    let log = sandbox.run_ok(&["clone"]);
+    assert!(log.contains("is already cloned"), "{}", log);
+
+    sandbox.commit("owner/repo", "CHANGELOG");
+    sandbox.run_ok(&["fetch"]);
+    assert!(!clone.join("CHANGELOG").exists());
+    let upstream = sandbox.git(&["rev-parse", "origin/main"], &clone);
+    let seed = sandbox.git(&["rev-parse", "HEAD"], &sandbox.path("seed/owner/repo"));
+    assert_eq!(upstream, seed);
+
+    let log = sandbox.run_ok(&["pull"]);
+    assert!(log.contains("Summary of pull: 1 pulled"), "{}", log);
+    assert!(log.contains("(1 new commits)"), "{}", log);
+    assert!(clone.join("CHANGELOG").exists());
+
+    let log = sandbox.run_ok(&["pull"]);
+    assert!(log.contains("Summary of pull: 1 up-to-date"), "{}", log);
+}
+
+#[test]
+fn colliding_clone_directories() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.upstream("other/repo");
+    sandbox.write_workspace(&["owner/repo", "other/repo"]);
+
+    sandbox.run_ok(&["clone"]);
+
+    let origin = |dir: &str| {
+        sandbox.git(
+            &["config", "--get", "remote.origin.url"],
+            &sandbox.path("clones").join(dir),
+        )
+    };
+    assert!(origin("repo").ends_with("/owner/repo"));
+    assert!(origin("repo-other").ends_with("/other/repo"));
+}
+
+#[test]
+fn add_ignores_known_repositories() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    fs::remove_file(sandbox.path("workspace.toml")).unwrap();
+
+    sandbox.run_ok(&["add", "--path", "clones/repo"]);
+    let workspace = sandbox.workspace_file();
+    assert!(workspace.contains("path = \"owner/repo\""), "{}", workspace);
+    assert!(
+        workspace.contains(&format!("provider = \"{}\"", sandbox.provider())),
+        "{}",
+        workspace
+    );
+
+    sandbox.run_ok(&["add", "--path", "clones/repo"]);
+    assert_eq!(sandbox.workspace_file(), workspace);
+}
+
+#[test]
+fn missing_workspace_file_is_an_error() {
+    let sandbox = Sandbox::new();
+
+    let output = sandbox.run(&["pull"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Could not read workspace file"));
+}
+
+#[test]
+fn missing_git_is_reported() {
+    let sandbox = Sandbox::new();
+    sandbox.write_workspace(&["owner/repo"]);
+    let empty = sandbox.path("empty");
+    fs::create_dir(&empty).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_workspace"))
+        .args(["--color", "never", "--file"])
+        .arg(sandbox.path("workspace.toml"))
+        .arg("sync")
+        .env("PATH", &empty)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stdout)
+            .contains("git executable not found, is it installed and on the PATH?"),
+        "{:?}",
+        output
+    );
+}
+
+#[test]
+fn import_urls_from_missing_file_is_an_error() {
+    let sandbox = Sandbox::new();
+    sandbox.write_workspace(&[]);
+
+    let output = sandbox.run(&["import-urls", "missing.txt"]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Could not read"));
+}
+
+#[test]
+fn stale_branches_merged_into_origin() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    // Merged into origin, but the local default branch is behind
+    sandbox.commit("owner/repo", "CHANGELOG");
+    sandbox.run_ok(&["fetch"]);
+    let clone = sandbox.path("clones/repo");
+    sandbox.git(&["branch", "feature", "origin/main"], &clone);
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+
+    let log = sandbox.run_ok(&["stale-branches", "--days", "0"]);
+    assert!(log.contains(" - feature"), "{}", log);
+}
+
+#[test]
+fn unwritable_state_file_is_no_error() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    fs::remove_file(sandbox.path("workspace.toml")).unwrap();
+    // A directory can't be replaced by the state file
+    fs::remove_file(sandbox.path(".workspace-state.toml")).ok();
+    fs::create_dir(sandbox.path(".workspace-state.toml")).unwrap();
+
+    let log = sandbox.run_ok(&["scan"]);
+    assert!(log.contains("Could not write the state file"), "{}", log);
+    assert!(sandbox.workspace_file().contains("path = \"owner/repo\""));
+}
+
+#[test]
+fn move_clone() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.upstream("owner/other");
+    sandbox.write_workspace(&["owner/repo", "owner/other"]);
+    sandbox.run_ok(&["clone"]);
+
+    let output = sandbox.run(&["mv", "--path", "owner/repo", "--to", "other"]);
+    assert!(!output.status.success());
+    assert!(sandbox.path("clones/repo/README").exists());
+
+    sandbox.run_ok(&["mv", "--path", "owner/repo", "--to", "moved/repo"]);
+    assert!(!sandbox.path("clones/repo").exists());
+    assert!(sandbox.path("clones/moved/repo/README").exists());
+    let workspace = sandbox.workspace_file();
+    assert!(workspace.contains("dir = \"moved/repo\""), "{}", workspace);
+
+    let log = sandbox.run_ok(&["pull"]);
+    assert!(log.contains("Summary of pull: 2 up-to-date"), "{}", log);
+}
+
+#[test]
+fn fail_fast_skips_the_remaining_repositories() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/missing", "owner/repo"]);
+
+    let output = sandbox.run(&["--jobs", "1", "--fail-fast", "clone"]);
+    let summary = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(summary.contains("1 skipped, 1 failed"), "{}", summary);
+    assert!(!sandbox.path("clones/repo").exists());
+
+    let output = sandbox.run(&["--jobs", "1", "clone"]);
+    assert!(!output.status.success());
+    assert!(sandbox.path("clones/repo").exists());
+}
+
+#[test]
+fn health_with_fewer_jobs_than_projects() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/a");
+    sandbox.upstream("owner/b");
+    sandbox.upstream("owner/c");
+    sandbox.write_workspace(&["owner/a", "owner/b", "owner/c"]);
+    sandbox.run_ok(&["clone"]);
+
+    let log = sandbox.run_ok(&["--jobs", "2", "health"]);
+    let rows: Vec<&str> = log
+        .lines()
+        .filter(|line| line.starts_with("owner/"))
+        .collect();
+    assert_eq!(rows.len(), 3, "{}", log);
+    for (row, path) in rows.iter().zip(&["owner/a", "owner/b", "owner/c"]) {
+        assert!(row.starts_with(path), "{}", log);
+        assert!(row.contains("yes"), "{}", log);
+    }
+}
+
+#[test]
+fn checkout_skips_repositories_without_the_branch() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/a");
+    sandbox.upstream("owner/b");
+    sandbox.git(&["branch", "feature"], &sandbox.path("upstream/owner/b"));
+    sandbox.write_workspace(&["owner/a", "owner/b"]);
+    sandbox.run_ok(&["clone"]);
+
+    let output = sandbox.run(&["checkout", "--branch", "feature"]);
+    let log = String::from_utf8_lossy(&output.stdout);
+    let summary = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}{}", log, summary);
+    assert!(log.contains("has no branch feature"), "{}", log);
+    assert!(summary.contains("1 skipped"), "{}", summary);
+    assert!(summary.contains("1 done"), "{}", summary);
+}
+
+#[test]
+fn yaml_workspace_round_trip() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    let yaml = format!(
+        "# Shared workspace\n\
+         default_cmd: [make]\n\
+         workspace:\n  \
+           - provider: \"{}\"\n    \
+             path: owner/repo\n    \
+             tags: [backend]\n    \
+             env:\n      \
+               MODE: debug build\n",
+        sandbox.provider()
+    );
+    fs::write(sandbox.path("workspace.yaml"), yaml).unwrap();
+    let run = |args: &[&str]| {
+        let output = sandbox.run_in(
+            &sandbox.dir,
+            &[&["--root", "clones", "--file", "workspace.yaml"], args].concat(),
+        );
+        let log = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(output.status.success(), "workspace {:?}:\n{}", args, log);
+        log
+    };
+
+    run(&["clone"]);
+    assert!(sandbox.path("clones/repo/README").exists());
+
+    run(&["disable", "--path", "owner/repo"]);
+    let saved = fs::read_to_string(sandbox.path("workspace.yaml")).unwrap();
+    assert!(saved.starts_with("# Shared workspace\n"), "{}", saved);
+    assert!(saved.contains("  - provider: \"file://"), "{}", saved);
+    assert!(saved.contains("    path: owner/repo\n"), "{}", saved);
+    assert!(saved.contains("    enabled: false\n"), "{}", saved);
+    assert!(saved.contains("      MODE: debug build\n"), "{}", saved);
+    assert!(!sandbox.path("workspace.toml").exists());
+
+    run(&["enable", "--path", "owner/repo"]);
+    let saved = fs::read_to_string(sandbox.path("workspace.yaml")).unwrap();
+    assert!(!saved.contains("enabled"), "{}", saved);
+    assert!(saved.contains("default_cmd:\n  - make\n"), "{}", saved);
+    assert!(saved.contains("    tags:\n      - backend\n"), "{}", saved);
+    let log = run(&["list", "--tag", "backend"]);
+    assert!(log.contains("owner/repo"), "{}", log);
+}
+
+#[test]
+fn mirrors_are_updated() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    let workspace = sandbox
+        .workspace_file()
+        .replace("cmd = []", "cmd = [\"false\"]\nmirror = true");
+    fs::write(sandbox.path("workspace.toml"), workspace).unwrap();
+
+    sandbox.run_ok(&["clone"]);
+    let mirror = sandbox.path("clones/repo");
+    assert_eq!(
+        sandbox.git(&["rev-parse", "--is-bare-repository"], &mirror),
+        "true"
+    );
+
+    sandbox.commit("owner/repo", "CHANGELOG");
+    let log = sandbox.run_ok(&["pull"]);
+    assert!(log.contains("Summary of pull: 1 pulled"), "{}", log);
+    let seed = sandbox.git(&["rev-parse", "HEAD"], &sandbox.path("seed/owner/repo"));
+    assert_eq!(sandbox.git(&["rev-parse", "main"], &mirror), seed);
+
+    let log = sandbox.run_ok(&["build"]);
+    assert!(log.contains("is a mirror, skipping build"), "{}", log);
+}
+
+#[test]
+fn off_branch_repositories() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.upstream("owner/other");
+    sandbox.write_workspace(&["owner/repo", "owner/other"]);
+    let workspace = sandbox
+        .workspace_file()
+        .replace("cmd = []", "cmd = []\nbranch = \"main\"");
+    fs::write(sandbox.path("workspace.toml"), workspace).unwrap();
+    sandbox.run_ok(&["clone"]);
+    sandbox.git(
+        &["checkout", "-q", "-b", "feature"],
+        &sandbox.path("clones/repo"),
+    );
+
+    let log = sandbox.run_ok(&["list", "--off-branch"]);
+    assert!(
+        log.contains("owner/repo (feature, expected main)"),
+        "{}",
+        log
+    );
+    assert!(!log.contains("owner/other"), "{}", log);
+
+    let log = sandbox.run_ok(&["status"]);
+    assert!(log.contains("feature (expected main)"), "{}", log);
+}
+
+#[test]
+fn porcelain_status() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.upstream("owner/other");
+    sandbox.write_workspace(&["owner/repo", "owner/other"]);
+    sandbox.run_ok(&["clone"]);
+    sandbox.write_workspace(&["owner/repo", "owner/other", "owner/missing"]);
+    sandbox.commit("owner/repo", "CHANGELOG");
+    sandbox.run_ok(&["fetch"]);
+    fs::write(sandbox.path("clones/other/README"), "changed\n").unwrap();
+
+    let output = sandbox.run(&["status", "--porcelain"]);
+    assert!(output.status.success(), "{:?}", output);
+    // Neither log lines nor repositories which are not cloned
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "owner/repo\tmain\t0\t1\tclean\nowner/other\tmain\t0\t0\tdirty\n"
+    );
+}
+
+#[test]
+fn no_save_lists_instead_of_saving() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    fs::remove_file(sandbox.path("workspace.toml")).unwrap();
+
+    let log = sandbox.run_ok(&["--no-save", "--format", "json", "scan"]);
+    assert!(log.contains("\"path\":\"owner/repo\""), "{}", log);
+    assert!(!sandbox.path("workspace.toml").exists());
+}
+
+#[test]
+fn add_records_clone_directory_outside_of_layout() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    fs::create_dir_all(sandbox.path("clones/nested")).unwrap();
+    fs::rename(
+        sandbox.path("clones/repo"),
+        sandbox.path("clones/nested/place"),
+    )
+    .unwrap();
+    fs::remove_file(sandbox.path("workspace.toml")).unwrap();
+
+    sandbox.run_ok(&["add", "--path", "clones/nested/place"]);
+    let workspace = sandbox.workspace_file();
+    assert!(
+        workspace.contains("dir = \"nested/place\""),
+        "{}",
+        workspace
+    );
+
+    let log = sandbox.run_ok(&["pull"]);
+    assert!(log.contains("Summary of pull: 1 up-to-date"), "{}", log);
+}
+
+#[test]
+fn workspace_file_is_found_from_subdirectories() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    let workspace = format!(
+        "root = \"clones\"\n\n[[workspace]]\nprovider = \"{}\"\npath = \"owner/repo\"\n",
+        sandbox.provider()
+    );
+    fs::write(sandbox.path("workspace.toml"), workspace).unwrap();
+    sandbox.run_ok(&["clone"]);
+
+    let output = sandbox.run_in(&sandbox.path("clones/repo"), &["pull"]);
+    let summary = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", summary);
+    assert!(
+        summary.contains("Summary of pull: 1 up-to-date"),
+        "{}",
+        summary
+    );
+    assert!(!sandbox.path("clones/repo/clones").exists());
+}
+
+#[test]
+fn deepen_and_unshallow_clones() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.commit("owner/repo", "a");
+    sandbox.commit("owner/repo", "b");
+    sandbox.write_workspace(&["owner/repo"]);
+    let url = format!("{}/owner/repo", sandbox.provider());
+    sandbox.git(
+        &["clone", "-q", "--depth", "1", &url, "repo"],
+        &sandbox.path("clones"),
+    );
+    let clone = sandbox.path("clones/repo");
+    let count = || sandbox.git(&["rev-list", "--count", "HEAD"], &clone);
+    assert_eq!(count(), "1");
+
+    sandbox.run_ok(&["fetch", "--deepen", "1"]);
+    assert_eq!(count(), "2");
+
+    sandbox.run_ok(&["fetch", "--unshallow"]);
+    assert_eq!(count(), "3");
+    // A complete clone is left alone instead of failing
+    sandbox.run_ok(&["fetch", "--unshallow"]);
+}
+
+#[test]
+fn destructive_operations_require_confirmation() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    let clone = sandbox.path("clones/repo");
+    fs::write(clone.join("README"), "changed").unwrap();
+    fs::write(clone.join("untracked"), "untracked").unwrap();
+
+    // Without a terminal nobody can confirm
+    assert!(!sandbox.run(&["reset"]).status.success());
+    sandbox.run_ok(&["clean"]);
+    assert!(clone.join("untracked").exists());
+
+    sandbox.run_ok(&["--yes", "reset"]);
+    assert_eq!(fs::read_to_string(clone.join("README")).unwrap(), "README");
+    sandbox.run_ok(&["--yes", "clean"]);
+    assert!(!clone.join("untracked").exists());
+}
+
+#[test]
+fn import_github_organization() {
+    let sandbox = Sandbox::new();
+    sandbox.write_workspace(&[]);
+    // curl serves the API response from a file, ignoring the query
+    let repos = sandbox.path("api/orgs/org/repos");
+    fs::create_dir_all(repos.parent().unwrap()).unwrap();
+    fs::write(
+        &repos,
+        r#"[{"full_name": "org/a", "owner": {"login": "org"}}, {"full_name": "org/b"}]"#,
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_workspace"))
+        .args(["--color", "never", "--file"])
+        .arg(sandbox.path("workspace.toml"))
+        .args(["import", "--org", "org"])
+        .env(
+            "WORKSPACE_GITHUB_API",
+            format!("file://{}", sandbox.path("api").display()),
+        )
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let workspace = sandbox.workspace_file();
+    assert!(workspace.contains("path = \"org/a\""), "{}", workspace);
+    assert!(workspace.contains("path = \"org/b\""), "{}", workspace);
+    assert!(workspace.contains("provider = \"github\""), "{}", workspace);
+}
+
+#[test]
+fn open_web_page_of_repository() {
+    let sandbox = Sandbox::new();
+    sandbox.write_workspace(&["owner/repo", "owner/other"]);
+    let open = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_workspace"))
+            .args(["--color", "never", "--file"])
+            .arg(sandbox.path("workspace.toml"))
+            .arg("open")
+            .args(args)
+            .env("BROWSER", "echo")
+            .output()
+            .unwrap()
+    };
+
+    let output = open(&["REPO", "--issues"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        stdout.contains(&format!("{}/owner/repo/issues\n", sandbox.provider())),
+        "{}",
+        stdout
+    );
+
+    let output = open(&["owner/other", "--owner"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        stdout.contains(&format!("{}/owner\n", sandbox.provider())),
+        "{}",
+        stdout
+    );
+
+    // several matches are listed instead of opening one of them
+    let output = open(&["owner"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    assert!(stdout.contains("2 repositories match"), "{}", stdout);
+    assert!(stdout.contains(" - owner/repo"), "{}", stdout);
+    assert!(stdout.contains(" - owner/other"), "{}", stdout);
+}
+
+#[test]
+fn log_of_all_clones() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.commit("owner/repo", "CHANGELOG");
+    sandbox.upstream("owner/other");
+    sandbox.write_workspace(&["owner/repo", "owner/other"]);
+    sandbox.run_ok(&["clone"]);
+
+    let output = sandbox.run(&["log", "-n", "1"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{:?}", output);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", stdout);
+    assert_eq!(lines[0], "owner/repo:");
+    assert!(lines[1].ends_with(" CHANGELOG"), "{}", stdout);
+    assert_eq!(lines[2], "owner/other:");
+    assert!(lines[3].ends_with(" README"), "{}", stdout);
+
+    // Repositories without commits in the range are left out
+    let output = sandbox.run(&["log", "--since", "2099-01-01"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout.is_empty(), "{:?}", output);
+}
+
+#[test]
+fn sorted_workspace_file() {
+    let sandbox = Sandbox::new();
+    sandbox.write_workspace(&["owner/c", "owner/b"]);
+    let workspace = format!("sort = true\n\n{}", sandbox.workspace_file());
+    fs::write(sandbox.path("workspace.toml"), workspace).unwrap();
+
+    let url = format!("{}/owner/a.git", sandbox.provider());
+    sandbox.run_ok(&["add", "--url", &url]);
+    // Added again after sorting, the existing entry is still detected
+    let log = sandbox.run_ok(&["add", "--url", &url]);
+    assert!(log.contains("is already part of the workspace"), "{}", log);
+    let workspace = sandbox.workspace_file();
+    let paths: Vec<&str> = workspace
+        .lines()
+        .filter(|line| line.starts_with("path = "))
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            "path = \"owner/a\"",
+            "path = \"owner/b\"",
+            "path = \"owner/c\""
+        ],
+        "{}",
+        workspace
+    );
+    assert!(workspace.starts_with("sort = true\n"), "{}", workspace);
+}
+
+#[test]
+fn diff_of_changed_clones() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.upstream("owner/other");
+    sandbox.write_workspace(&["owner/repo", "owner/other"]);
+    sandbox.run_ok(&["clone"]);
+    fs::write(sandbox.path("clones/repo/README"), "changed\n").unwrap();
+    fs::write(sandbox.path("clones/repo/NEW"), "new\n").unwrap();
+
+    let output = sandbox.run(&["diff"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{:?}", output);
+    // Clean repositories are left out
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "owner/repo:", "{}", stdout);
+    assert!(lines[1].starts_with("  README | "), "{}", stdout);
+    assert!(lines.contains(&"  ?? NEW"), "{}", stdout);
+    assert!(!stdout.contains("owner/other"), "{}", stdout);
+
+    let output = sandbox.run(&["diff", "--name-only"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout, "owner/repo:\n  README\n  NEW\n");
+}
+
+#[test]
+fn disabled_repositories_are_skipped() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.upstream("owner/other");
+    sandbox.write_workspace(&["owner/repo", "owner/other"]);
+
+    sandbox.run_ok(&["disable", "--path", "owner/other"]);
+    assert!(sandbox.workspace_file().contains("enabled = false"));
+    let output = sandbox.run_ok(&["sync"]);
+    assert!(output.contains("\"owner/other\" is disabled"), "{}", output);
+    assert!(output.contains("1 cloned, 1 skipped"), "{}", output);
+    assert!(!sandbox.path("clones/other").exists());
+
+    sandbox.run_ok(&["enable", "--path", "owner/other"]);
+    assert!(!sandbox.workspace_file().contains("enabled"));
+    sandbox.run_ok(&["sync"]);
+    assert!(sandbox.path("clones/other").exists());
+}
+
+#[test]
+fn add_url_without_clone() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    let url = format!("{}/owner/repo.git", sandbox.provider());
+
+    sandbox.run_ok(&["add", "--url", &url, "--tag", "remote"]);
+    let workspace = sandbox.workspace_file();
+    assert!(workspace.contains("path = \"owner/repo\""), "{}", workspace);
+    assert!(workspace.contains("tags = [\"remote\"]"), "{}", workspace);
+    assert!(!sandbox.path("clones/repo").exists());
+
+    let log = sandbox.run_ok(&["add", "--url", &url]);
+    assert!(log.contains("already part of the workspace"), "{}", log);
+    let output = sandbox.run(&["add", "--url", "not a url"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("not a valid remote url"), "{}", stdout);
+
+    sandbox.run_ok(&["clone"]);
+    assert!(sandbox.path("clones/repo/README").exists());
+}
+
+#[test]
+fn unterminated_quotes_in_build_commands_are_rejected() {
+    let sandbox = Sandbox::new();
+    sandbox.write_workspace(&["owner/repo"]);
+    let url = format!("{}/owner/other.git", sandbox.provider());
+
+    let output = sandbox.run(&["add", "--url", &url, "--cmd", "make \"all"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("Invalid command"), "{}", stderr);
+
+    let output = sandbox.run(&["set-cmd", "--match", "*", "--cmd", "make 'all"]);
+    assert!(!output.status.success());
+    assert!(!sandbox.workspace_file().contains("make"));
+}
+
+#[test]
+fn exit_status_and_json_summary() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/missing", "owner/repo"]);
+    let summary = sandbox.path("summary.json");
+    let summary_arg = summary.to_str().unwrap();
+
+    let output = sandbox.run(&["--summary-json", summary_arg, "clone"]);
+    assert_eq!(output.status.code(), Some(2), "{:?}", output);
+    let json = fs::read_to_string(&summary).unwrap();
+    assert!(
+        json.starts_with(r#"{"operation":"clone","total":2,"failed":1,"#),
+        "{}",
+        json
+    );
+    assert!(json.contains(r#"{"path":"owner/repo","status":"cloned","error":null,"seconds":"#));
+    assert!(json.contains(r#"{"path":"owner/missing","status":"failed","error":""#));
+
+    sandbox.write_workspace(&["owner/missing"]);
+    assert_eq!(sandbox.run(&["clone"]).status.code(), Some(3));
+}
+
+#[test]
+fn slowest_repositories() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/a");
+    sandbox.upstream("owner/b");
+    sandbox.write_workspace(&["owner/a", "owner/b"]);
+
+    let log = sandbox.run_ok(&["--slowest", "1", "clone"]);
+    let timings: Vec<&str> = log
+        .lines()
+        .skip_while(|line| !line.ends_with("Timings of clone:"))
+        .skip(1)
+        .take_while(|line| !line.ends_with("s total"))
+        .collect();
+    assert_eq!(timings.len(), 1, "{}", log);
+    assert!(timings[0].contains("s owner/"), "{}", log);
+}
+
+#[cfg(unix)]
+#[test]
+fn relative_build_scripts() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    let workspace = sandbox
+        .workspace_file()
+        .replace("cmd = []", "cmd = [\"./scripts/build.sh\"]");
+    fs::write(sandbox.path("workspace.toml"), workspace).unwrap();
+    let script = sandbox.path("clones/repo/scripts/build.sh");
+    fs::create_dir_all(script.parent().unwrap()).unwrap();
+    fs::write(&script, "#!/bin/sh\ntouch built\n").unwrap();
+
+    let output = sandbox.run(&["build"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert!(stdout.contains("is not executable"), "{}", stdout);
+
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    sandbox.run_ok(&["build"]);
+    assert!(sandbox.path("clones/repo/built").exists());
+}
+
+#[test]
+fn default_build_command() {
+    let sandbox = Sandbox::new();
+    for path in ["owner/repo", "owner/own", "owner/none"] {
+        sandbox.upstream(path);
+    }
+    sandbox.write_workspace(&["owner/repo", "owner/own", "owner/none"]);
+    sandbox.run_ok(&["clone"]);
+    let workspace = sandbox.workspace_file();
+    let workspace = format!("default_cmd = [\"touch\", \"default\"]\n\n{}", workspace)
+        .replace(
+            "path = \"owner/own\"\ncmd = []",
+            "path = \"owner/own\"\ncmd = [\"touch\", \"own\"]",
+        )
+        .replace(
+            "path = \"owner/none\"\ncmd = []",
+            "path = \"owner/none\"\ncmd = []\nno_build = true",
+        );
+    fs::write(sandbox.path("workspace.toml"), workspace).unwrap();
+
+    sandbox.run_ok(&["build"]);
+    assert!(sandbox.path("clones/repo/default").exists());
+    assert!(sandbox.path("clones/own/own").exists());
+    assert!(!sandbox.path("clones/own/default").exists());
+    assert!(!sandbox.path("clones/none/default").exists());
+}
+
+#[test]
+fn verify_remotes() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.upstream("fork/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    sandbox.run_ok(&["verify"]);
+
+    let fork = format!("{}/fork/repo", sandbox.provider());
+    let clone = sandbox.path("clones/repo");
+    sandbox.git(&["remote", "set-url", "origin", &fork], &clone);
+    let output = sandbox.run(&["verify"]);
+    assert!(!output.status.success(), "{:?}", output);
+
+    sandbox.run_ok(&["verify", "--fix"]);
+    let workspace = sandbox.workspace_file();
+    assert!(workspace.contains("path = \"fork/repo\""), "{}", workspace);
+    assert!(!workspace.contains("dir ="), "{}", workspace);
+    sandbox.run_ok(&["verify"]);
+}
+
+#[test]
+fn strict_pull_and_fetch_check_the_remote_url() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    sandbox.run_ok(&["pull", "--strict"]);
+
+    // The same repository, but from somewhere else
+    let upstream = format!("{}/owner/repo", sandbox.provider());
+    let elsewhere = sandbox.path("elsewhere/owner/repo");
+    sandbox.git(
+        &["clone", "--bare", &upstream, elsewhere.to_str().unwrap()],
+        &sandbox.path(""),
+    );
+    let clone = sandbox.path("clones/repo");
+    let url = format!("file://{}", elsewhere.display());
+    sandbox.git(&["remote", "set-url", "origin", &url], &clone);
+
+    for command in ["pull", "fetch", "sync"] {
+        let output = sandbox.run(&[command, "--strict"]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(!output.status.success(), "{:?}", output);
+        assert!(
+            stdout.contains(&format!("origin points to {}, expected {}", url, upstream)),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn scan_skips_workspaceignore_patterns() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/a");
+    sandbox.upstream("owner/b");
+    sandbox.write_workspace(&["owner/a", "owner/b"]);
+    sandbox.run_ok(&["clone"]);
+    fs::remove_file(sandbox.path("workspace.toml")).unwrap();
+    fs::write(sandbox.path("clones/.workspaceignore"), "# not ours\nb/\n").unwrap();
+
+    sandbox.run_ok(&["scan"]);
+    let workspace = sandbox.workspace_file();
+    assert!(workspace.contains("path = \"owner/a\""), "{}", workspace);
+    assert!(!workspace.contains("owner/b"), "{}", workspace);
+}
+
+#[test]
+fn push_unpushed_commits() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.upstream("owner/other");
+    sandbox.write_workspace(&["owner/repo", "owner/other"]);
+    sandbox.run_ok(&["clone"]);
+    let clone = sandbox.path("clones/repo");
+    fs::write(clone.join("CHANGELOG"), "changes").unwrap();
+    sandbox.git(&["add", "CHANGELOG"], &clone);
+    sandbox.git(&["commit", "-q", "-m", "Add changelog"], &clone);
+
+    let log = sandbox.run_ok(&["push", "--dry-run"]);
+    assert!(
+        log.contains("Summary of push: 1 pushed, 1 up-to-date"),
+        "{}",
+        log
+    );
+    let upstream = sandbox.path("upstream/owner/repo");
+    assert_eq!(
+        sandbox.git(&["rev-list", "--count", "main"], &upstream),
+        "1"
+    );
+
+    let log = sandbox.run_ok(&["push"]);
+    assert!(
+        log.contains("Summary of push: 1 pushed, 1 up-to-date"),
+        "{}",
+        log
+    );
+    assert_eq!(
+        sandbox.git(&["rev-list", "--count", "main"], &upstream),
+        "2"
+    );
+
+    let log = sandbox.run_ok(&["push"]);
+    assert!(log.contains("Summary of push: 2 up-to-date"), "{}", log);
+}
+
+#[test]
+fn push_skips_detached_head() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    let clone = sandbox.path("clones/repo");
+    sandbox.git(&["checkout", "-q", "--detach"], &clone);
+
+    let log = sandbox.run_ok(&["push", "--set-upstream"]);
+    assert!(
+        log.contains("has a detached HEAD, skipping push"),
+        "{}",
+        log
+    );
+    assert!(log.contains("Summary of push: 1 skipped"), "{}", log);
+}
+
+#[test]
+fn branches_without_upstream_are_skipped() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    sandbox.write_workspace(&["owner/repo"]);
+    sandbox.run_ok(&["clone"]);
+    let clone = sandbox.path("clones/repo");
+    sandbox.git(&["branch", "--unset-upstream"], &clone);
+
+    let log = sandbox.run_ok(&["pull"]);
+    assert!(log.contains("Summary of pull: 1 skipped"), "{}", log);
+    assert!(
+        log.contains("no upstream configured for branch main"),
+        "{}",
+        log
+    );
+
+    sandbox.commit("owner/repo", "CHANGELOG");
+    let log = sandbox.run_ok(&["pull", "--set-upstream"]);
+    assert!(log.contains("Summary of pull: 1 pulled"), "{}", log);
+    let upstream = sandbox.git(&["rev-parse", "--abbrev-ref", "main@{u}"], &clone);
+    assert_eq!(upstream, "origin/main");
+}
+
+#[test]
+fn rescan_skips_known_clones() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/a");
+    sandbox.upstream("owner/b");
+    sandbox.write_workspace(&["owner/a", "owner/b"]);
+    sandbox.run_ok(&["clone"]);
+    let workspace = sandbox.workspace_file();
+
+    let log = sandbox.run_ok(&["scan"]);
+    assert!(
+        log.contains("Added 0 new repositories, skipped 2 known clones"),
+        "{}",
+        log
+    );
+    assert_eq!(sandbox.workspace_file(), workspace);
+}
+
+#[test]
+fn scan_infers_build_commands() {
+    let sandbox = Sandbox::new();
+    for path in ["owner/a", "owner/b", "owner/c"] {
+        sandbox.upstream(path);
+    }
+    sandbox.write_workspace(&["owner/a", "owner/b", "owner/c"]);
+    sandbox.run_ok(&["clone"]);
+    fs::remove_file(sandbox.path("workspace.toml")).unwrap();
+    fs::write(sandbox.path("clones/a/Cargo.toml"), "").unwrap();
+    fs::write(sandbox.path("clones/b/Makefile"), "").unwrap();
+
+    sandbox.run_ok(&["scan", "--infer-cmd"]);
+    let workspace = sandbox.workspace_file();
+    let cmd = |path: &str| {
+        let entry = workspace
+            .split("[[workspace]]")
+            .find(|entry| entry.contains(&format!("path = \"{}\"", path)));
+        entry
+            .unwrap()
+            .lines()
+            .find(|line| line.starts_with("cmd = "))
+            .map(String::from)
+    };
+    assert_eq!(
+        cmd("owner/a").as_deref(),
+        Some("cmd = [\"cargo\", \"build\"]"),
+        "{}",
+        workspace
+    );
+    assert_eq!(
+        cmd("owner/b").as_deref(),
+        Some("cmd = [\"make\"]"),
+        "{}",
+        workspace
+    );
+    assert_eq!(cmd("owner/c").as_deref(), Some("cmd = []"), "{}", workspace);
+}
+
+#[test]
+fn clones_are_kept_at_their_ref() {
+    let sandbox = Sandbox::new();
+    sandbox.upstream("owner/repo");
+    let seed = sandbox.path("seed/owner/repo");
+    sandbox.git(&["tag", "v1"], &seed);
+    sandbox.git(&["push", "-q", "origin", "v1"], &seed);
+    sandbox.commit("owner/repo", "CHANGELOG");
+    sandbox.write_workspace(&["owner/repo"]);
+    let workspace = sandbox.workspace_file();
+    let write_ref = |name: &str| {
+        let pinned = workspace.replace("cmd = []", &format!("cmd = []\nref = \"{}\"", name));
+        fs::write(sandbox.path("workspace.toml"), pinned).unwrap();
+    };
+    write_ref("v1");
+
+    sandbox.run_ok(&["clone"]);
+    let clone = sandbox.path("clones/repo");
+    assert!(!clone.join("CHANGELOG").exists());
+    let log = sandbox.run_ok(&["sync"]);
+    assert!(log.contains("Summary of sync: 1 up-to-date"), "{}", log);
+    assert!(!clone.join("CHANGELOG").exists());
+
+    // A branch is pulled like the `branch` of a project, once the pinned clone may be moved
+    write_ref("main");
+    sandbox.commit("owner/repo", "NEWS");
+    let log = sandbox.run_ok(&["sync"]);
+    assert!(log.contains("Summary of sync: 1 skipped"), "{}", log);
+    sandbox.run_ok(&["--force-pull-detached", "sync"]);
+    assert_eq!(
+        sandbox.git(&["rev-parse", "--abbrev-ref", "HEAD"], &clone),
+        "main"
+    );
+    assert!(clone.join("NEWS").exists());
+}